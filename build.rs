@@ -0,0 +1,182 @@
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha384};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Frontend assets are vendored in `public/vendor`: their Subresource
+// Integrity hashes are computed here, and the build fails if a page still
+// loads a script or a stylesheet from another site.
+//
+// Every file in `public` also gets a name with a hash of its contents, so it
+// can be cached forever; `crate::assets` maps one name to the other.
+//
+// The commit and time of the build end up in `/version`.
+
+const VENDORED: &[(&str, &str)] = &[
+    ("HIGHLIGHT_JS_INTEGRITY", "public/vendor/highlight.js/highlight.min.js"),
+    ("HIGHLIGHT_RUST_INTEGRITY", "public/vendor/highlight.js/languages/rust.min.js"),
+    ("HIGHLIGHT_GO_INTEGRITY", "public/vendor/highlight.js/languages/go.min.js"),
+    ("HIGHLIGHT_CSS_INTEGRITY", "public/vendor/highlight.js/atom-one-dark.min.css"),
+];
+
+// Tags whose `src` or `href` is loaded and run or applied by the browser.
+const TAGS: &[&str] = &["<Script", "<script", "<Link", "<link", "<Stylesheet"];
+
+fn integrity(path: &str) -> String {
+    let contents = fs::read(path).unwrap_or_else(|err| panic!("could not read {path}: {err}"));
+    let hash = Sha384::digest(&contents);
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(hash)
+    )
+}
+
+fn files(dir: &Path, extension: Option<&str>, found: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files(&path, extension, found);
+        } else if extension.is_none_or(|extension| path.extension().is_some_and(|ext| ext == extension)) {
+            found.push(path);
+        }
+    }
+}
+
+// `vendor/highlight.js/highlight.min.js` becomes
+// `vendor/highlight.js/highlight.min.0123456789abcdef.js`.
+fn fingerprinted(path: &str, contents: &[u8]) -> String {
+    let hash = Sha256::digest(contents);
+    let hash: String = hash[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+    let name_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}.{hash}{}", &path[..dot], &path[dot..])
+        }
+        _ => format!("{path}.{hash}"),
+    }
+}
+
+fn asset_manifest() -> String {
+    println!("cargo:rerun-if-changed=public");
+    let mut found = Vec::new();
+    files(Path::new("public"), None, &mut found);
+    found.sort();
+
+    let mut manifest = String::from("pub const ASSETS: &[(&str, &str)] = &[\n");
+    for path in found {
+        let contents = fs::read(&path).unwrap();
+        let url = path
+            .strip_prefix("public")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .replace('\\', "/");
+        let url = format!("/{}", url.trim_start_matches('/'));
+        manifest.push_str(&format!(
+            "    ({url:?}, {:?}),\n",
+            fingerprinted(&url, &contents)
+        ));
+    }
+    manifest.push_str("];\n");
+    manifest
+}
+
+fn external_urls(path: &Path) -> Vec<(usize, String)> {
+    let source = fs::read_to_string(path).unwrap();
+    let mut found = Vec::new();
+    for tag in TAGS {
+        for (start, _) in source.match_indices(tag) {
+            let end = source[start..]
+                .find('>')
+                .map_or(source.len(), |end| start + end);
+            let element = &source[start..end];
+            // `<link rel="canonical">` and the like are fine.
+            if tag.ends_with("ink")
+                && !["stylesheet", "preload", "modulepreload"]
+                    .iter()
+                    .any(|rel| element.contains(&format!("rel=\"{rel}\"")))
+            {
+                continue;
+            }
+            for attr in ["src=\"", "href=\""] {
+                for (offset, _) in element.match_indices(attr) {
+                    let value = &element[offset + attr.len()..];
+                    if value.starts_with("http:") || value.starts_with("https:") || value.starts_with("//") {
+                        let line = source[..start + offset].lines().count();
+                        let url = value.split('"').next().unwrap_or_default();
+                        found.push((line, url.to_string()));
+                    }
+                }
+            }
+        }
+    }
+    found
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
+// BLOG_GIT_SHA and SOURCE_DATE_EPOCH win over git and the clock, for builds
+// outside a checkout or that must be reproducible.
+fn build_info() {
+    println!("cargo:rerun-if-env-changed=BLOG_GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    // A missing file would run this on every build.
+    let reference = git(&["symbolic-ref", "-q", "HEAD"]).unwrap_or_default();
+    for name in ["HEAD", "packed-refs", &reference] {
+        if let Some(path) = git(&["rev-parse", "--git-path", name]).filter(|path| Path::new(path).is_file()) {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    let sha = env::var("BLOG_GIT_SHA")
+        .ok()
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    let time = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+    println!("cargo:rustc-env=BLOG_GIT_SHA={sha}");
+    println!("cargo:rustc-env=BLOG_BUILD_TIME={time}");
+}
+
+fn main() {
+    let mut constants = String::new();
+    for (name, path) in VENDORED {
+        println!("cargo:rerun-if-changed={path}");
+        constants.push_str(&format!(
+            "pub const {name}: &str = \"{}\";\n",
+            integrity(path)
+        ));
+    }
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out.join("integrity.rs"), constants).unwrap();
+    fs::write(out.join("assets.rs"), asset_manifest()).unwrap();
+    build_info();
+
+    println!("cargo:rerun-if-changed=src");
+    let mut sources = Vec::new();
+    files(Path::new("src"), Some("rs"), &mut sources);
+    let mut external = Vec::new();
+    for file in sources {
+        for (line, url) in external_urls(&file) {
+            external.push(format!("{}:{line}: {url}", file.display()));
+        }
+    }
+    if !external.is_empty() {
+        panic!(
+            "pages must not load scripts or stylesheets from other sites, vendor them in public/vendor:\n{}",
+            external.join("\n")
+        );
+    }
+}
@@ -1,39 +1,111 @@
 
+use crate::assets::asset_url;
 use crate::error_template::{AppError, ErrorTemplate};
+use crate::search::SearchHit;
+use leptos::nonce::use_nonce;
+use leptos::server_fn::codec::GetUrl;
 use leptos::*;
 use leptos_meta::*;
 use leptos_router::*;
-use std::fs;
-use serde_yaml; 
 use serde::{Serialize, Deserialize};
 
+pub const SITE_NAME: &str = "Tomás Varas Blog";
+
+// Subresource Integrity hashes of the files in `public/vendor`, see build.rs.
+include!(concat!(env!("OUT_DIR"), "/integrity.rs"));
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 
 pub struct PostMeta {
-    descripcion: String,
-    titulo: String,
-    fecha: String,
-    archivo: String
+    // Optional in the `.yml`, filled with an excerpt of the post when missing.
+    // Shown on the home page and as the meta description, there are no feeds.
+    #[serde(default)]
+    pub descripcion: String,
+    pub titulo: String,
+    pub fecha: String,
+    pub archivo: String,
+    // Only used by the search for now, there are no tag pages.
+    #[serde(default)]
+    pub etiquetas: Vec<String>,
+    // Computed from the Markdown when the post is loaded.
+    #[serde(default)]
+    pub palabras: usize,
+    #[serde(default)]
+    pub minutos_lectura: usize,
+    // Drafts are only published with `blog serve --drafts`.
+    #[serde(default)]
+    pub draft: bool,
+    // Cover image, relative to the post directory or an absolute URL.
+    // Relative paths are resolved to the post assets when the post is loaded.
+    #[serde(default)]
+    pub portada: Option<String>,
+    #[serde(default)]
+    pub portada_alt: Option<String>,
+}
+
+// A post on the home page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostSummary {
+    pub meta: PostMeta,
+    // Smaller version of the cover, computed by the server.
+    pub miniatura: Option<String>,
 }
 
-#[derive(Params, PartialEq)]
-struct PostParams {
-    archivo: Option<String>
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostContent {
+    pub meta: PostMeta,
+    pub html: String,
+    // Absolute URL of the cover, or of the generated card for posts without
+    // one, when the site URL is known.
+    pub og_image: Option<String>,
 }
 
 #[component]
 pub fn App() -> impl IntoView {
     // Provides context that manages stylesheets, titles, meta tags, etc.
     provide_meta_context();
+
+    #[cfg(feature = "hydrate")]
+    if is_static_site() {
+        provide_context(RouterIntegrationContext::new(StaticNavigation));
+    }
+
+    // The policy goes with the nonce leptos put on the inline scripts. That
+    // nonce is only for this response, so shared caches must not keep it.
+    #[cfg(feature = "ssr")]
+    if let Some(response) = use_context::<leptos_axum::ResponseOptions>() {
+        use crate::assets::PRIVATE_PAGE;
+        use crate::security::{content_security_policy, csp_header};
+        use http::{header, HeaderValue};
+
+        let nonce = use_nonce();
+        if nonce.is_some() {
+            response.insert_header(header::CACHE_CONTROL, HeaderValue::from_static(PRIVATE_PAGE));
+        }
+        if let Some(policy) = content_security_policy(nonce.as_deref()) {
+            response.insert_header(csp_header(), policy);
+        }
+    }
     view! {
         <Link
             rel="stylesheet"
-            href="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/styles/atom-one-dark.css"
+            href=asset_url("/vendor/highlight.js/atom-one-dark.min.css").to_string()
+            integrity=HIGHLIGHT_CSS_INTEGRITY
+        />
+        <Script
+            src=asset_url("/vendor/highlight.js/highlight.min.js").to_string()
+            integrity=HIGHLIGHT_JS_INTEGRITY
         />
-        <Script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/highlight.min.js"/>
-        <Script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/languages/rust.min.js"/>
-        <Script src="https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/languages/go.min.js"/>
+        <Script
+            src=asset_url("/vendor/highlight.js/languages/rust.min.js").to_string()
+            integrity=HIGHLIGHT_RUST_INTEGRITY
+        />
+        <Script
+            src=asset_url("/vendor/highlight.js/languages/go.min.js").to_string()
+            integrity=HIGHLIGHT_GO_INTEGRITY
+        />
+        <Link rel="icon" href=asset_url("/favicon.ico").to_string()/>
+        // Renamed to the fingerprinted file by leptos when `hash-files` is on.
         <Stylesheet id="leptos" href="/pkg/blog.css"/>
         // sets the document title
         <Title text="Blog de Tomás"/>
@@ -48,9 +120,12 @@ pub fn App() -> impl IntoView {
                 <Routes>
                     <Route path="" view=BlogHomePage/>
                     <Route path="/secret" view=|| view! { <p>secreto</p> }/>
-                    <Route path="/post/:archivo" view=Post/>
+                    // Rendered once the post is loaded, so its title and
+                    // meta tags make it into the <head> for crawlers.
+                    <Route path="/post/:archivo" view=Post ssr=SsrMode::Async/>
                     <Route path="/about" view=AboutPage/>
                     <Route path="/projects" view=ProjectsPage/>
+                    <Route path="/search" view=SearchPage/>
                 </Routes>
             </Layout>
         </Router>
@@ -58,52 +133,114 @@ pub fn App() -> impl IntoView {
 }
 
 
-#[server(GetPost)]
-async fn get_post(name: String) -> Result<String, ServerFnError> {
-    let file_content = fs::read_to_string(format!("posts/{}/{}", name.split(".").collect::<Vec<&str>>()[0], name)).unwrap();
-    let post = markdown::to_html(&file_content);
-    Ok(post)
-}
+// Names the server functions count their calls under, see `crate::metrics`.
+// They are counted inside, rendering on the server calls them without a
+// request.
+#[cfg(feature = "ssr")]
+pub const SERVER_FNS: &[&str] = &["GetPost", "GetPostsMeta", "SearchPosts"];
 
-#[server(GetPostsMeta)]
- async fn get_posts_meta() -> Result<Vec<PostMeta>, ServerFnError> {
-    let paths = fs::read_dir("posts").unwrap();
-    let mut posts_meta: Vec<PostMeta> = Vec::new();
-   
-    for p in paths {
-      
-        let pval = p.unwrap();
-       
-        let post_dirs = fs::read_dir(pval.path()).unwrap();
-        
-        for dir in post_dirs {
-            
-            let file_path = format!("{}", dir?.path().display());
+// Content errors name files on the server, so they are logged here and
+// clients only learn whether the post exists.
+#[cfg(feature = "ssr")]
+fn content_error(err: crate::content::ContentError) -> ServerFnError {
+    use crate::content::ContentError;
 
-                if file_path.ends_with("yml") {
-
-                    let file_content = fs::read_to_string(file_path).unwrap();
-
-                    let meta = serde_yaml::from_str(&file_content).unwrap();
-
-                    posts_meta.push(meta);
+    if let ContentError::NotFound(_) = err {
+        return ServerFnError::ServerError("post not found".to_string());
+    }
+    leptos::logging::error!("could not load posts: {err}");
+    ServerFnError::ServerError("the posts could not be loaded".to_string())
+}
 
-                }
-        }
+// Loaded with GET so the JSON gets an ETag, see `crate::conditional`.
+#[server(name = GetPost, input = GetUrl)]
+async fn get_post(name: String) -> Result<PostContent, ServerFnError> {
+    use crate::content::{absolute_url, assets_url, content_index};
+    use crate::directives::{escape_html, Directives};
+    use crate::render::render_markdown;
+    use crate::sanitize::ContentSource;
+
+    crate::metrics::count_server_fn("GetPost");
+    let index = content_index().map_err(content_error)?;
+    let post = index.post(&name).map_err(content_error)?;
+    let mut html = render_markdown(
+        &post.source,
+        &Directives::default(),
+        ContentSource::Post,
+        Some(&assets_url(&post.meta.archivo)),
+    );
+    // Goes through `responsive_images` like any other image in the post, but
+    // it is at the top of the page, so it isn't loaded lazily.
+    if let Some(portada) = &post.meta.portada {
+        let alt = post.meta.portada_alt.as_deref().unwrap_or_default();
+        html = format!(
+            "<img class=\"w-full rounded-lg mb-6\" src=\"{}\" alt=\"{}\" loading=\"eager\" fetchpriority=\"high\">\n{html}",
+            escape_html(portada),
+            escape_html(alt)
+        );
     }
+    let html = crate::images::responsive_images(&html, post);
+    let og_image = match &post.meta.portada {
+        Some(portada) => absolute_url(portada),
+        None => absolute_url(&crate::og::og_url(&post.meta.archivo)),
+    };
+    Ok(PostContent {
+        meta: post.meta.clone(),
+        html,
+        og_image,
+    })
+}
 
-    Ok(posts_meta)
+#[server(name = GetPostsMeta, input = GetUrl)]
+async fn get_posts_meta() -> Result<Vec<PostSummary>, ServerFnError> {
+    use crate::content::content_index;
+    use crate::images::thumbnail;
+
+    crate::metrics::count_server_fn("GetPostsMeta");
+    let index = content_index().map_err(content_error)?;
+    Ok(index
+        .posts
+        .iter()
+        .map(|post| PostSummary {
+            meta: post.meta.clone(),
+            miniatura: thumbnail(post),
+        })
+        .collect())
+}
 
+#[server(SearchPosts)]
+async fn search_posts(query: String) -> Result<Vec<SearchHit>, ServerFnError> {
+    use crate::content::content_index;
+
+    crate::metrics::count_server_fn("SearchPosts");
+    Ok(content_index().map_err(content_error)?.search.search(&query))
 }
 
 
 #[component]
-fn BlogPost(title: String, content: String, date: String, link: String) -> impl IntoView {
+fn BlogPost(
+    title: String,
+    content: String,
+    date: String,
+    reading_time: usize,
+    link: String,
+    image: Option<String>,
+    // The only content of the link around the image, so it names the link.
+    image_alt: String,
+) -> impl IntoView {
     view! {
         <article class="bg-white shadow-md rounded-lg overflow-hidden mb-8">
+            {image
+                .map(|image| {
+                    view! {
+                        <a href=link.clone()>
+                            <img src=image alt=image_alt loading="lazy" class="w-full h-48 object-cover"/>
+                        </a>
+                    }
+                })}
             <div class="p-6">
                 <h2 class="text-2xl font-bold mb-2">{title.clone()}</h2>
-                <p class="text-gray-600 mb-4">{date}</p>
+                <p class="text-gray-600 mb-4">{date} " · " {reading_time} " min de lectura"</p>
                 <p class="text-gray-700 mb-4">{content}</p>
                 <a href=link class="text-blue-600 hover:text-blue-800 font-semibold">
                     Leer más
@@ -123,23 +260,56 @@ fn Post() -> impl IntoView {
     };
 
     let post = create_resource(
-        move || archivo(),
+        archivo,
         |archivo| async move { get_post(archivo).await }
     );
 
+    // Islands inside the post can only be mounted once its HTML is in the DOM.
+    #[cfg(feature = "hydrate")]
+    create_effect(move |_| {
+        if post.with(|post| matches!(post, Some(Ok(_)))) {
+            request_animation_frame(crate::islands::mount_islands);
+        }
+    });
+
     view! {
         <Suspense fallback=move || {
             view! { <p>"Cargando posts..."</p> }
         }>
 
             {move || match post.get() {
-                Some(Ok(content)) => {
-                    view! { <div class="blog-post" inner_html=content></div> }
+                Some(Ok(post)) => {
+                    let og_image_alt = post
+                        .meta
+                        .portada_alt
+                        .clone()
+                        .unwrap_or_else(|| post.meta.titulo.clone());
+                    view! {
+                        <div>
+                            <Title text=post.meta.titulo/>
+                            <Meta name="description" content=post.meta.descripcion/>
+                            {post
+                                .og_image
+                                .map(|image| {
+                                    view! {
+                                        <Meta property="og:image" content=image/>
+                                        <Meta property="og:image:alt" content=og_image_alt/>
+                                    }
+                                })}
+                            <p class="text-gray-600 max-w-3xl mx-auto px-4">
+                                {post.meta.fecha} " · " {post.meta.minutos_lectura}
+                                " min de lectura"
+                            </p>
+                            <div class="blog-post" inner_html=post.html></div>
+                        </div>
+                    }
                 }
                 Some(Err(_)) => view! { <div>"Error cargando post"</div> },
                 None => view! { <div>"Cargando..."</div> },
             }}
-            <script>hljs.highlightAll();</script>
+            <script nonce=use_nonce()>
+                "hljs.highlightAll();"
+            </script>
         </Suspense>
     }
 }
@@ -163,13 +333,20 @@ fn BlogHomePage() -> impl IntoView {
                             .map(|v| {
                                 v.into_iter()
                                     .rev()
-                                    .map(|n| {
+                                    .map(|PostSummary { meta: n, miniatura }| {
+                                        let image_alt = n
+                                            .portada_alt
+                                            .clone()
+                                            .unwrap_or_else(|| n.titulo.clone());
                                         view! {
                                             <BlogPost
                                                 title=n.titulo
                                                 content=n.descripcion
                                                 date=n.fecha
+                                                reading_time=n.minutos_lectura
                                                 link=format!("/post/{}", n.archivo)
+                                                image=miniatura.or(n.portada)
+                                                image_alt
                                             />
                                         }
                                     })
@@ -183,6 +360,137 @@ fn BlogHomePage() -> impl IntoView {
     }
 }
 
+// Whether the page is part of an exported site, served without the server
+// functions behind it.
+pub fn is_static_site() -> bool {
+    #[cfg(feature = "ssr")]
+    return use_context::<http::request::Parts>()
+        .is_some_and(|parts| parts.extensions.get::<crate::export::StaticSite>().is_some());
+    #[cfg(all(feature = "hydrate", not(feature = "ssr")))]
+    return document()
+        .query_selector("meta[name=\"blog-static\"]")
+        .ok()
+        .flatten()
+        .is_some();
+    #[cfg(not(any(feature = "ssr", feature = "hydrate")))]
+    return false;
+}
+
+// Exported sites have no server functions to load a page from, so the router
+// leaves navigation to the browser, which loads the exported HTML.
+#[cfg(feature = "hydrate")]
+struct StaticNavigation;
+
+#[cfg(feature = "hydrate")]
+impl History for StaticNavigation {
+    fn location(&self) -> ReadSignal<LocationChange> {
+        BrowserIntegration {}.location()
+    }
+
+    fn navigate(&self, change: &LocationChange) {
+        let location = window().location();
+        let _ = if change.replace {
+            location.replace(&change.value)
+        } else {
+            location.assign(&change.value)
+        };
+    }
+}
+
+#[component]
+fn SearchPage() -> impl IntoView {
+    let query = use_query_map();
+    let q = move || query.with(|query| query.get("q").cloned().unwrap_or_default());
+
+    // The server searches, so results are in the page. Exported sites have no
+    // server, there the browser searches the serialised index.
+    let results = if is_static_site() {
+        create_local_resource(q, |q| async move {
+            if q.trim().is_empty() {
+                return Ok(Vec::new());
+            }
+            #[cfg(feature = "hydrate")]
+            if let Some(index) = crate::search::client_index().await {
+                return Ok(index.search(&q));
+            }
+            Err(ServerFnError::ServerError("the search index could not be loaded".to_string()))
+        })
+    } else {
+        create_resource(q, |q| async move {
+            if q.trim().is_empty() {
+                return Ok(Vec::new());
+            }
+            search_posts(q).await
+        })
+    };
+
+    view! {
+        <div class="max-w-3xl mx-auto px-4">
+            <h1 class="text-4xl font-bold mb-6 text-gray-800">"Buscar"</h1>
+            <Form method="GET" action="/search" class="mb-8">
+                <input
+                    type="search"
+                    name="q"
+                    value=q
+                    placeholder="¿Qué estás buscando?"
+                    class="w-full border border-gray-300 rounded-lg px-4 py-2"
+                />
+            </Form>
+            <Suspense fallback=move || {
+                view! { <p>"Buscando..."</p> }
+            }>
+                {move || {
+                    results
+                        .get()
+                        .map(|result| match result {
+                            Ok(hits) if hits.is_empty() && !q().trim().is_empty() => {
+                                view! { <p class="text-gray-600">"No se encontraron posts."</p> }
+                                    .into_view()
+                            }
+                            Ok(hits) => {
+                                hits.into_iter()
+                                    .map(|hit| view! { <SearchResult hit/> })
+                                    .collect_view()
+                            }
+                            Err(_) => view! { <p>"Error buscando posts"</p> }.into_view(),
+                        })
+                }}
+
+            </Suspense>
+        </div>
+    }
+}
+
+#[component]
+fn SearchResult(hit: SearchHit) -> impl IntoView {
+    view! {
+        <article class="bg-white shadow-md rounded-lg p-6 mb-6">
+            <h2 class="text-2xl font-bold mb-2">
+                <a
+                    href=format!("/post/{}", hit.archivo)
+                    class="text-blue-600 hover:text-blue-800"
+                >
+                    {hit.titulo}
+                </a>
+            </h2>
+            <p class="text-gray-600 mb-2">{hit.fecha}</p>
+            <p class="text-gray-700">
+                {hit
+                    .snippet
+                    .into_iter()
+                    .map(|part| {
+                        if part.highlight {
+                            view! { <mark class="bg-yellow-200">{part.text}</mark> }.into_view()
+                        } else {
+                            part.text.into_view()
+                        }
+                    })
+                    .collect_view()}
+            </p>
+        </article>
+    }
+}
+
 #[component]
 fn AboutPage() -> impl IntoView {
     view! {
@@ -410,7 +718,7 @@ fn Layout(children: Children) -> impl IntoView {
         <div class="flex flex-col bg-gray-100 min-h-screen">
             <header class="bg-white shadow-md">
                 <div class="container mx-auto px-4 py-6">
-                    <h1 class="text-3xl font-bold text-gray-800">"Tomás Varas Blog"</h1>
+                    <h1 class="text-3xl font-bold text-gray-800">{SITE_NAME}</h1>
                     <nav class="mt-4">
                         <a href="/" class="text-gray-600 hover:text-gray-800 mr-4">
                             "Inicio"
@@ -418,9 +726,12 @@ fn Layout(children: Children) -> impl IntoView {
                         <a href="/about" class="text-gray-600 hover:text-gray-800 mr-4">
                             "Acerca"
                         </a>
-                        <a href="/projects" class="text-gray-600 hover:text-gray-800">
+                        <a href="/projects" class="text-gray-600 hover:text-gray-800 mr-4">
                             "Proyectos"
                         </a>
+                        <a href="/search" class="text-gray-600 hover:text-gray-800">
+                            "Buscar"
+                        </a>
                     </nav>
                 </div>
             </header>
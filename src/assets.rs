@@ -0,0 +1,50 @@
+// Files in `public` are also served under a name with a hash of their
+// contents, see build.rs. Pages link to those names, so browsers can keep
+// them forever and still get the new file after a deploy.
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+pub const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+// Files whose URL doesn't change with their contents.
+pub const SHORT: &str = "public, max-age=3600";
+// Pages change with the posts, they are only kept for a minute before the
+// browser checks them with the server, see `crate::conditional`.
+pub const PAGE: &str = "public, max-age=60";
+// The same, for pages with inline scripts carrying a nonce.
+pub const PRIVATE_PAGE: &str = "private, max-age=60";
+
+// The fingerprinted URL of a file in `public`, or the path itself when there
+// is no such file.
+pub fn asset_url(path: &str) -> &str {
+    ASSETS
+        .iter()
+        .find(|(original, _)| *original == path)
+        .map_or(path, |(_, fingerprinted)| fingerprinted)
+}
+
+// The file in `public` behind a fingerprinted URL.
+pub fn asset_path(url: &str) -> Option<&'static str> {
+    ASSETS
+        .iter()
+        .find(|(_, fingerprinted)| *fingerprinted == url)
+        .map(|(original, _)| *original)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprinted_urls_lead_back_to_the_file() {
+        let url = asset_url("/favicon.ico");
+        assert!(url.starts_with("/favicon.") && url.ends_with(".ico"));
+        assert_ne!(url, "/favicon.ico");
+        assert_eq!(asset_path(url), Some("/favicon.ico"));
+    }
+
+    #[test]
+    fn other_paths_are_left_alone() {
+        assert_eq!(asset_url("/pkg/blog.css"), "/pkg/blog.css");
+        assert_eq!(asset_path("/favicon.ico"), None);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::content::{assets_url, load_post_dir, meta_path, ContentError, ContentIndex, Post};
+use crate::directives::Directives;
+use crate::render::{code_languages, links, unknown_directives, LinkTarget};
+use crate::scaffold::DATE_FORMAT;
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where `/favicon.ico` and other absolute asset links are looked up.
+pub const PUBLIC_DIR: &str = "public";
+
+// Languages in the highlight.js bundle in `public/vendor`, with their aliases.
+const KNOWN_LANGUAGES: &[&str] = &[
+    "bash", "sh", "c", "h", "cpp", "cc", "c++", "h++", "hpp", "hh", "hxx", "cxx", "csharp", "cs",
+    "c#", "css", "diff", "patch", "go", "golang", "ini", "toml", "java", "jsp", "javascript", "js",
+    "jsx", "mjs", "cjs", "json", "kotlin", "kt", "kts", "less", "lua", "makefile", "mk", "mak",
+    "make", "xml", "html", "xhtml", "rss", "atom", "xjb", "xsd", "xsl", "plist", "wsf", "svg",
+    "markdown", "md", "mkdown", "mkd", "objectivec", "mm", "objc", "obj-c", "obj-c++",
+    "objective-c++", "perl", "pl", "pm", "php", "php-template", "plaintext", "text", "txt",
+    "python", "py", "gyp", "ipython", "python-repl", "pycon", "r", "ruby", "rb", "gemspec",
+    "podspec", "thor", "irb", "rust", "rs", "scss", "shell", "console", "shellsession", "sql",
+    "swift", "typescript", "ts", "tsx", "vbnet", "vb", "yaml", "yml",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+struct Checker<'a> {
+    // Route patterns of the site, like `/about` or `/post/:archivo`.
+    routes: &'a [String],
+    public_dir: &'a Path,
+    problems: Vec<Problem>,
+    external: Vec<ExternalLink>,
+}
+
+// An `http(s)` link, only checked on request since it needs the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalLink {
+    pub path: PathBuf,
+    pub line: usize,
+    pub url: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub external: Vec<ExternalLink>,
+}
+
+impl Checker<'_> {
+    fn finish(self) -> Report {
+        Report {
+            problems: self.problems,
+            external: self.external,
+        }
+    }
+
+    fn report(&mut self, path: &Path, line: Option<usize>, message: String) {
+        self.problems.push(Problem {
+            path: path.to_path_buf(),
+            line,
+            message,
+        });
+    }
+
+    fn check_meta(&mut self, post: &Post, meta_path: &Path) {
+        if let Some(portada) = &post.meta.portada {
+            let found = match portada.strip_prefix(&assets_url(&post.meta.archivo)) {
+                Some(path) => post.asset(&percent_decode_str(path).decode_utf8_lossy()).is_some(),
+                None => is_external(portada) || self.public_dir.join(portada.trim_start_matches('/')).is_file(),
+            };
+            if !found {
+                self.report(meta_path, None, format!("portada {portada} does not exist"));
+            }
+        }
+        if post.meta.titulo.trim().is_empty() {
+            self.report(meta_path, None, "titulo is empty".to_string());
+        }
+        if chrono::NaiveDate::parse_from_str(&post.meta.fecha, DATE_FORMAT).is_err() {
+            self.report(
+                meta_path,
+                None,
+                format!("fecha {:?} is not a dd/mm/yyyy date", post.meta.fecha),
+            );
+        }
+    }
+
+    fn check_source(&mut self, post: &Post, posts: &[Post]) {
+        let path = post.dir.join(&post.meta.archivo);
+        for (name, line) in unknown_directives(&post.source, &Directives::default()) {
+            self.report(&path, Some(line), format!("unknown directive {name}"));
+        }
+
+        for (lang, line) in code_languages(&post.source) {
+            if !KNOWN_LANGUAGES.contains(&lang.to_lowercase().as_str()) {
+                self.report(&path, Some(line), format!("unknown code block language {lang}"));
+            }
+        }
+
+        for link in links(&post.source) {
+            if is_external(&link.url) {
+                self.external.push(ExternalLink {
+                    path: path.clone(),
+                    line: link.line,
+                    url: link.url,
+                });
+            }
+        }
+
+        let find_post = |archivo: &str| posts.iter().find(|post| post.meta.archivo == archivo);
+        for link in dangling_links(post, find_post, self.routes, self.public_dir) {
+            let kind = if link.image { "image" } else { "link" };
+            self.report(&path, Some(link.line), format!("broken {kind} {}", link.url));
+        }
+    }
+}
+
+pub fn is_external(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+// Links of a post that point nowhere: `/post/...` links to posts or post
+// assets that don't exist, absolute links that are neither a route nor a file
+// in the site root, and relative links to files missing from the post
+// directory.
+pub fn dangling_links<'a>(
+    post: &Post,
+    find_post: impl Fn(&str) -> Option<&'a Post>,
+    routes: &[String],
+    public_dir: &Path,
+) -> Vec<LinkTarget> {
+    links(&post.source)
+        .into_iter()
+        .filter(|link| {
+            let url = link.url.split(['#', '?']).next().unwrap_or_default();
+            if url.is_empty() || url.contains(':') {
+                return false;
+            }
+            let url = &*percent_decode_str(url).decode_utf8_lossy();
+            let found = if let Some(rest) = url.strip_prefix("/post/") {
+                match rest.split_once("/assets/") {
+                    Some((archivo, path)) => find_post(archivo).is_some_and(|post| post.asset(path).is_some()),
+                    None => find_post(rest).is_some(),
+                }
+            } else if let Some(absolute) = url.strip_prefix('/') {
+                routes.iter().any(|route| route == url) || public_dir.join(absolute).is_file()
+            } else {
+                // Relative links are served as post assets, see `Post::asset`.
+                post.asset(url.trim_start_matches("./")).is_some()
+            };
+            !found
+        })
+        .collect()
+}
+
+// Dangling links in the published posts, logged as warnings when the server starts.
+pub fn index_link_problems(index: &ContentIndex, routes: &[String], public_dir: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for post in &index.posts {
+        let path = post.dir.join(&post.meta.archivo);
+        for link in dangling_links(post, |archivo| index.post(archivo).ok(), routes, public_dir) {
+            let kind = if link.image { "image" } else { "link" };
+            problems.push(Problem {
+                path: path.clone(),
+                line: Some(link.line),
+                message: format!("broken {kind} {}", link.url),
+            });
+        }
+    }
+    problems
+}
+
+fn describe(err: &ContentError) -> (PathBuf, String) {
+    match err {
+        ContentError::Io { path, source } => (path.clone(), format!("could not read the file: {source}")),
+        ContentError::Meta { path, source } => (path.clone(), format!("invalid metadata: {source}")),
+        ContentError::MissingMeta(path) => (path.clone(), "no .yml metadata file".to_string()),
+        ContentError::NotFound(archivo) => (PathBuf::from(archivo), "post not found".to_string()),
+    }
+}
+
+// Loads every post the same way the server does and collects everything
+// wrong with them instead of stopping at the first error.
+pub fn check(root: &Path, routes: &[String], public_dir: &Path) -> Report {
+    let mut checker = Checker {
+        routes,
+        public_dir,
+        problems: Vec::new(),
+        external: Vec::new(),
+    };
+
+    let mut dirs: Vec<PathBuf> = match fs::read_dir(root) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(err) => {
+            checker.report(root, None, format!("could not read the directory: {err}"));
+            return checker.finish();
+        }
+    };
+    dirs.sort();
+
+    let mut posts = Vec::new();
+    let mut archivos: HashMap<String, PathBuf> = HashMap::new();
+    for dir in dirs {
+        match load_post_dir(&dir) {
+            Ok(post) => {
+                if let Some(other) = archivos.get(&post.meta.archivo) {
+                    let message = format!(
+                        "archivo {} is also used by {}",
+                        post.meta.archivo,
+                        other.display()
+                    );
+                    checker.report(&dir, None, message);
+                } else {
+                    archivos.insert(post.meta.archivo.clone(), dir.clone());
+                }
+                posts.push(post);
+            }
+            Err(err) => {
+                let (path, message) = describe(&err);
+                checker.report(&path, None, message);
+            }
+        }
+    }
+
+    for post in &posts {
+        let meta_path = meta_path(&post.dir).unwrap_or_else(|_| post.dir.clone());
+        checker.check_meta(post, &meta_path);
+        checker.check_source(post, &posts);
+    }
+
+    checker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn reports_problems_in_posts() {
+        let root = std::env::temp_dir().join(format!("blog-check-{}", std::process::id()));
+        let posts = root.join("posts");
+        write(
+            &posts.join("a/a.yml"),
+            "titulo: A\nfecha: 2024-01-01\narchivo: a.md\nportada: falta.png\n",
+        );
+        write(
+            &posts.join("a/a.md"),
+            "Un [enlace roto](/post/nada.md), [otro post](/post/b.md), [about](/about) y \
+             [favicon](/favicon.ico).\n\n::desconocida\n\n```rust\n::tampoco\n```\n\n\
+             ```klingon\nx\n```\n\n[fuera](https://example.com)\n",
+        );
+        write(&posts.join("b/b.yml"), "titulo: B\nfecha: 01/02/2024\narchivo: b.md\n");
+        write(&posts.join("b/b.md"), ":::note\n![foto](foto.png) ![doc](doc.pdf)\n:::\n");
+        write(&posts.join("b/doc.pdf"), "");
+        write(&root.join("public/favicon.ico"), "");
+
+        let routes = ["/".to_string(), "/about".to_string(), "/post/:archivo".to_string()];
+        let report = check(&posts, &routes, &root.join("public"));
+        fs::remove_dir_all(&root).unwrap();
+
+        let problems: Vec<String> = report
+            .problems
+            .iter()
+            .map(|problem| problem.to_string().replace(&posts.display().to_string(), ""))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                "/a/a.yml: portada /post/a.md/assets/falta.png does not exist",
+                "/a/a.yml: fecha \"2024-01-01\" is not a dd/mm/yyyy date",
+                "/a/a.md:3: unknown directive desconocida",
+                "/a/a.md:9: unknown code block language klingon",
+                "/a/a.md:1: broken link /post/nada.md",
+                "/b/b.md:2: broken image foto.png",
+            ]
+        );
+        assert_eq!(report.external.len(), 1);
+        assert_eq!(report.external[0].url, "https://example.com");
+        assert_eq!(report.external[0].line, 13);
+    }
+
+    #[test]
+    fn links_to_post_assets_need_the_file() {
+        let root = std::env::temp_dir().join(format!("blog-check-assets-{}", std::process::id()));
+        write(&root.join("a/a.yml"), "titulo: A\nfecha: 01/02/2024\narchivo: a.md\n");
+        write(
+            &root.join("a/a.md"),
+            "[pdf](/post/b.md/assets/doc%20final.pdf) [falta](/post/b.md/assets/falta.pdf) \
+             [nada](/post/nada.md/assets/doc.pdf)\n",
+        );
+        write(&root.join("b/b.yml"), "titulo: B\nfecha: 01/02/2024\narchivo: b.md\n");
+        write(&root.join("b/b.md"), "B\n");
+        write(&root.join("b/doc final.pdf"), "");
+
+        let report = check(&root, &[], &root);
+        fs::remove_dir_all(&root).unwrap();
+
+        let messages: Vec<&str> = report.problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["broken link /post/b.md/assets/falta.pdf", "broken link /post/nada.md/assets/doc.pdf"]
+        );
+    }
+
+    #[test]
+    fn relative_links_need_a_served_asset() {
+        let root = std::env::temp_dir().join(format!("blog-check-relative-{}", std::process::id()));
+        write(&root.join("a/a.yml"), "titulo: A\nfecha: 01/02/2024\narchivo: a.md\n");
+        write(
+            &root.join("a/a.md"),
+            "[foto](./foto.png) [otro](../b/foto.png) [fuente](a.md) [meta](a.yml)\n",
+        );
+        write(&root.join("a/foto.png"), "");
+        write(&root.join("b/b.yml"), "titulo: B\nfecha: 01/02/2024\narchivo: b.md\n");
+        write(&root.join("b/b.md"), "B\n");
+        write(&root.join("b/foto.png"), "");
+
+        let report = check(&root, &[], &root);
+        fs::remove_dir_all(&root).unwrap();
+
+        let messages: Vec<&str> = report.problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["broken link ../b/foto.png", "broken link a.md", "broken link a.yml"]
+        );
+    }
+
+    #[test]
+    fn reports_posts_that_do_not_load() {
+        let root = std::env::temp_dir().join(format!("blog-check-broken-{}", std::process::id()));
+        write(&root.join("sin_meta/post.md"), "Hola\n");
+        write(&root.join("mala/mala.yml"), "titulo: [\n");
+
+        let report = check(&root, &[], &root);
+        fs::remove_dir_all(&root).unwrap();
+
+        let messages: Vec<&str> = report.problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("invalid metadata"));
+        assert_eq!(messages[1], "no .yml metadata file");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::content::POSTS_DIR;
+use crate::link_cache::LINK_CACHE_PATH;
+use crate::security::{DEFAULT_CSP, DEFAULT_HSTS, DEFAULT_PERMISSIONS_POLICY, DEFAULT_REFERRER_POLICY};
+use clap::{Args, Parser, Subcommand};
+use http::uri::Authority;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "blog", about = "Blog de Tomás")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Directory with one subdirectory per post
+    #[arg(long, global = true, default_value = POSTS_DIR)]
+    pub content_dir: PathBuf,
+
+    /// Public URL of the site, used for absolute links such as OpenGraph images
+    #[arg(long, global = true, env = "BLOG_SITE_URL")]
+    pub site_url: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the web server (the default)
+    Serve(Box<ServeArgs>),
+    /// Render the whole site to static files
+    Export(ExportArgs),
+    /// Write gzip, Brotli and Zstandard copies of the text files in the site root
+    Compress(CompressArgs),
+    /// Check every post for problems, exits with an error if any is found
+    Check(CheckArgs),
+    /// Create a new post
+    New(NewArgs),
+    /// Check that the server on this machine is ready, for container health checks
+    Health(HealthArgs),
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ServeArgs {
+    /// Address to listen on, overrides LEPTOS_SITE_ADDR
+    #[arg(long)]
+    pub addr: Option<SocketAddr>,
+
+    /// Also publish posts marked as drafts
+    #[arg(long)]
+    pub drafts: bool,
+
+    /// Keep rendered pages in memory until the posts change
+    #[arg(long)]
+    pub page_cache: bool,
+
+    /// Most pages kept in the page cache
+    #[arg(long, default_value_t = 512, requires = "page_cache")]
+    pub page_cache_pages: usize,
+
+    /// Most megabytes of pages kept in the page cache
+    #[arg(long, default_value_t = 32, requires = "page_cache")]
+    pub page_cache_mb: usize,
+
+    /// Token for the admin endpoints: the metrics and purging the page cache
+    #[arg(long, env = "BLOG_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// Certificate chain in PEM, serves HTTPS instead of HTTP
+    #[arg(long, env = "BLOG_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// Private key of the certificate in PEM
+    #[arg(long, env = "BLOG_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Also listen for plain HTTP on this address, redirecting to HTTPS
+    #[arg(long, env = "BLOG_REDIRECT_ADDR", requires = "tls_cert")]
+    pub redirect_addr: Option<SocketAddr>,
+
+    /// Host and port plain HTTP is redirected to, defaults to those of an
+    /// https --site-url, or to the host asked for and the port of --addr
+    #[arg(long, env = "BLOG_REDIRECT_TO", requires = "redirect_addr")]
+    pub redirect_to: Option<Authority>,
+
+    #[command(flatten)]
+    pub security: SecurityArgs,
+}
+
+// Every header can be left out by passing an empty value.
+#[derive(Debug, Args)]
+pub struct SecurityArgs {
+    /// Content-Security-Policy, `{nonce}` is replaced by the nonce of the page
+    #[arg(long, env = "BLOG_CSP", default_value = DEFAULT_CSP, hide_default_value = true)]
+    pub csp: String,
+
+    /// Send the policy as Content-Security-Policy-Report-Only
+    #[arg(long, env = "BLOG_CSP_REPORT_ONLY")]
+    pub csp_report_only: bool,
+
+    /// Strict-Transport-Security
+    #[arg(long, env = "BLOG_HSTS", default_value = DEFAULT_HSTS)]
+    pub hsts: String,
+
+    /// Referrer-Policy
+    #[arg(long, env = "BLOG_REFERRER_POLICY", default_value = DEFAULT_REFERRER_POLICY)]
+    pub referrer_policy: String,
+
+    /// Permissions-Policy
+    #[arg(long, env = "BLOG_PERMISSIONS_POLICY", default_value = DEFAULT_PERMISSIONS_POLICY)]
+    pub permissions_policy: String,
+}
+
+impl Default for SecurityArgs {
+    fn default() -> Self {
+        SecurityArgs {
+            csp: DEFAULT_CSP.to_string(),
+            csp_report_only: false,
+            hsts: DEFAULT_HSTS.to_string(),
+            referrer_policy: DEFAULT_REFERRER_POLICY.to_string(),
+            permissions_policy: DEFAULT_PERMISSIONS_POLICY.to_string(),
+        }
+    }
+}
+
+// Reads the same settings as `serve`, to find it.
+#[derive(Debug, Args)]
+pub struct HealthArgs {
+    /// Address the server listens on, overrides LEPTOS_SITE_ADDR
+    #[arg(long)]
+    pub addr: Option<SocketAddr>,
+
+    /// The server has a certificate, so it is asked over HTTPS
+    #[arg(long, env = "BLOG_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Output directory
+    #[arg(default_value = "dist")]
+    pub out_dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct CompressArgs {
+    /// Directory to compress, defaults to LEPTOS_SITE_ROOT
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Also check links to other sites
+    #[arg(long)]
+    pub external: bool,
+
+    /// Only use cached results for links to other sites
+    #[arg(long, requires = "external")]
+    pub offline: bool,
+
+    /// File where the results for links to other sites are cached
+    #[arg(long, default_value = LINK_CACHE_PATH)]
+    pub link_cache: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct NewArgs {
+    /// Title of the post
+    pub title: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("blog").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn serves_by_default() {
+        let cli = parse(&[]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.content_dir, PathBuf::from(POSTS_DIR));
+    }
+
+    #[test]
+    fn global_options_go_after_the_command_too() {
+        let cli = parse(&["export", "salida", "--content-dir", "otros"]).unwrap();
+        assert_eq!(cli.content_dir, PathBuf::from("otros"));
+        let Some(Command::Export(args)) = cli.command else {
+            panic!("not an export");
+        };
+        assert_eq!(args.out_dir, PathBuf::from("salida"));
+    }
+
+    #[test]
+    fn dependent_flags_need_their_pair() {
+        assert!(parse(&["check", "--offline"]).is_err());
+        assert!(parse(&["check", "--external", "--offline"]).is_ok());
+        assert!(parse(&["serve", "--page-cache-pages", "10"]).is_err());
+    }
+
+    #[test]
+    fn redirects_go_to_a_host_and_port() {
+        let tls = ["serve", "--tls-cert", "c.pem", "--tls-key", "k.pem", "--redirect-addr", "0.0.0.0:80"];
+        let cli = parse(&[&tls[..], &["--redirect-to", "blog.example.com:8443"]].concat()).unwrap();
+        let Some(Command::Serve(args)) = cli.command else {
+            panic!("not serve");
+        };
+        assert_eq!(args.redirect_to.unwrap().port_u16(), Some(8443));
+        assert!(parse(&[&tls[..], &["--redirect-to", "https://blog.example.com/"]].concat()).is_err());
+        assert!(parse(&["serve", "--redirect-to", "blog.example.com"]).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Gzip, Brotli and Zstandard copies of text files, written next to them as
+// `.gz`, `.br` and `.zst`. The server sends them instead of compressing on
+// every request, and so do most static hosts.
+
+const EXTENSIONS: &[&str] = &[
+    "html", "css", "js", "mjs", "wasm", "json", "svg", "xml", "txt", "ico", "map",
+];
+// Smaller files gain nothing.
+const MIN_SIZE: u64 = 1024;
+
+#[derive(Debug, Error)]
+#[error("{path}: {source}")]
+pub struct CompressError {
+    path: PathBuf,
+    source: std::io::Error,
+}
+
+fn io_error(path: &Path) -> impl Fn(std::io::Error) -> CompressError + '_ {
+    move |source| CompressError {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+fn gzip(contents: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(contents)?;
+    encoder.finish()
+}
+
+fn brotli(contents: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 11,
+        ..Default::default()
+    };
+    brotli::BrotliCompress(&mut &contents[..], &mut compressed, &params)?;
+    Ok(compressed)
+}
+
+fn zstd(contents: &[u8]) -> std::io::Result<Vec<u8>> {
+    zstd::encode_all(contents, 19)
+}
+
+type Encoder = fn(&[u8]) -> std::io::Result<Vec<u8>>;
+
+const ENCODINGS: &[(&str, Encoder)] = &[("gz", gzip), ("br", brotli), ("zst", zstd)];
+
+fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext))
+}
+
+fn files(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), CompressError> {
+    for entry in fs::read_dir(dir).map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.path();
+        if path.is_dir() {
+            files(&path, found)?;
+        } else if is_compressible(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Compresses every text file under `dir` whose copies are missing or older
+// than it. A copy that wouldn't be smaller is not written. Returns the
+// number of files with a new copy.
+pub fn precompress(dir: &Path) -> Result<usize, CompressError> {
+    let mut found = Vec::new();
+    files(dir, &mut found)?;
+
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut compressed = 0;
+    for path in found {
+        let metadata = fs::metadata(&path).map_err(io_error(&path))?;
+        if metadata.len() < MIN_SIZE {
+            continue;
+        }
+        let mut contents = None;
+        let mut written = false;
+        for (extension, encode) in ENCODINGS {
+            let mut target = path.as_os_str().to_owned();
+            target.push(".");
+            target.push(extension);
+            let target = PathBuf::from(target);
+            if modified(&target).is_some_and(|cached| Some(cached) >= modified(&path)) {
+                continue;
+            }
+            if contents.is_none() {
+                contents = Some(fs::read(&path).map_err(io_error(&path))?);
+            }
+            let contents = contents.as_deref().unwrap_or_default();
+            let encoded = encode(contents).map_err(io_error(&path))?;
+            if encoded.len() < contents.len() {
+                fs::write(&target, encoded).map_err(io_error(&target))?;
+                written = true;
+            } else if target.exists() {
+                fs::remove_file(&target).map_err(io_error(&target))?;
+            }
+        }
+        if written {
+            compressed += 1;
+        }
+    }
+    Ok(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blog-compress-{name}-{}", std::process::id()));
+        fs::create_dir_all(dir.join("pkg")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn text_files_get_copies_that_decompress_to_them() {
+        let dir = temp_dir("copies");
+        let css = "body { color: red; }\n".repeat(100);
+        fs::write(dir.join("pkg/blog.css"), &css).unwrap();
+        fs::write(dir.join("small.js"), "let a = 1;").unwrap();
+        fs::write(dir.join("foto.png"), "x".repeat(2000)).unwrap();
+
+        let compressed = precompress(&dir).unwrap();
+        let again = precompress(&dir).unwrap();
+        let read = |ext: &str| fs::read(dir.join(format!("pkg/blog.css.{ext}"))).unwrap();
+        let mut gzip = String::new();
+        flate2::read::GzDecoder::new(&read("gz")[..]).read_to_string(&mut gzip).unwrap();
+        let mut brotli = Vec::new();
+        brotli::BrotliDecompress(&mut &read("br")[..], &mut brotli).unwrap();
+        let zstd = zstd::decode_all(&read("zst")[..]).unwrap();
+        let others = (dir.join("small.js.gz").exists(), dir.join("foto.png.gz").exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((compressed, again), (1, 0));
+        assert_eq!(gzip, css);
+        assert_eq!(brotli, css.as_bytes());
+        assert_eq!(zstd, css.as_bytes());
+        assert_eq!(others, (false, false));
+    }
+
+    #[test]
+    fn copies_that_wouldnt_be_smaller_are_left_out() {
+        let dir = temp_dir("random");
+        // Bytes from a generator, nothing to compress.
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let random: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        fs::write(dir.join("pkg/blog.wasm"), random).unwrap();
+
+        let compressed = precompress(&dir).unwrap();
+        let gzip = dir.join("pkg/blog.wasm.gz").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(compressed, 0);
+        assert!(!gzip);
+    }
+}
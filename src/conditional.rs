@@ -0,0 +1,208 @@
+use crate::content::{content_index, Stamp};
+use crate::health::GIT_SHA;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use leptos::LeptosOptions;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Pages and JSON only change when the posts do, or with a new build. Their
+// ETag is computed from the build, the files under the posts directory and
+// the route, so it survives restarts and a matching `If-None-Match` needs no
+// comparison of the body. It is weak: the compression layer changes the
+// bytes, not what they mean.
+
+// What is deployed: the commit and time of the build, and the hashes
+// cargo-leptos gave the JS, WASM and CSS, which change without the server
+// being rebuilt when only the styles do.
+#[derive(Clone)]
+pub struct Build(Arc<str>);
+
+impl Build {
+    pub fn new(options: &LeptosOptions) -> Self {
+        let hashes = std::env::current_exe()
+            .ok()
+            .filter(|_| options.hash_files)
+            .and_then(|exe| fs::read_to_string(exe.with_file_name(&options.hash_file)).ok())
+            .unwrap_or_default();
+        Build(format!("{GIT_SHA}:{}:{hashes}", env!("BLOG_BUILD_TIME")).into())
+    }
+}
+
+fn etag(build: &Build, (entries, modified): Stamp, route: &str) -> String {
+    let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let hash = Sha256::digest(format!("{}:{entries}:{modified}:{route}", build.0).as_bytes());
+    let hash: String = hash[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("W/\"{hash}\"")
+}
+
+fn built() -> SystemTime {
+    let seconds = env!("BLOG_BUILD_TIME").parse().unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+// HTTP dates have no fractions of a second. A new build may change every
+// page, even if the posts didn't.
+fn last_modified(modified: SystemTime) -> SystemTime {
+    let modified = modified.max(built());
+    let seconds = modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn matches_etag(headers: &HeaderMap, etag: &str) -> Option<bool> {
+    let tags = headers.get(header::IF_NONE_MATCH)?.to_str().unwrap_or_default();
+    Some(
+        tags.trim() == "*"
+            || tags
+                .split(',')
+                .any(|tag| tag.trim().trim_start_matches("W/") == etag.trim_start_matches("W/")),
+    )
+}
+
+fn unmodified_since(headers: &HeaderMap, modified: SystemTime) -> bool {
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|since| httpdate::parse_http_date(since.to_str().ok()?).ok())
+        .is_some_and(|since| modified <= since)
+}
+
+fn is_content(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|kind| kind.to_str().ok())
+        .is_some_and(|kind| kind.starts_with("text/html") || kind.starts_with("application/json"))
+}
+
+pub async fn conditional_get(State(build): State<Build>, request: Request, next: Next) -> Response {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    // Read before rendering, so the ETag is that of the posts the page is
+    // rendered from. The index only looks at the files once a second, static
+    // files don't pay for it.
+    let Ok(index) = content_index() else {
+        return next.run(request).await;
+    };
+    let route = request
+        .uri()
+        .path_and_query()
+        .map_or(request.uri().path(), |route| route.as_str())
+        .to_string();
+    let etag = etag(&build, index.stamp(), &route);
+    let modified = last_modified(index.modified());
+    let headers = [
+        (header::ETAG, HeaderValue::from_str(&etag).unwrap()),
+        (
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap(),
+        ),
+    ];
+
+    // Only answered once the route has rendered content, a 304 for `*` or an
+    // old tag must not stand in for a 404 or a file. `If-Modified-Since` is
+    // ignored when there is an `If-None-Match`.
+    let unmodified = matches_etag(request.headers(), &etag)
+        .unwrap_or_else(|| unmodified_since(request.headers(), modified));
+
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::OK || !is_content(&response) {
+        return response;
+    }
+    if unmodified {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    response.headers_mut().extend(headers);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::response::Html;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    fn app(build: &str) -> Router {
+        Router::new()
+            .route("/page", get(|| async { Html("<p>hola</p>") }))
+            .route("/file.txt", get(|| async { "hola" }))
+            .layer(middleware::from_fn_with_state(Build(build.into()), conditional_get))
+    }
+
+    async fn get_with(app: Router, path: &str, headers: &[(header::HeaderName, &str)]) -> Response {
+        let mut request = Request::get(path);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn pages_get_a_weak_etag_and_files_none() {
+        let page = get_with(app("a"), "/page", &[]).await;
+        let file = get_with(app("a"), "/file.txt", &[]).await;
+
+        let etag = page.headers()[header::ETAG].to_str().unwrap();
+        assert!(etag.starts_with("W/\""), "{etag}");
+        assert!(page.headers().contains_key(header::LAST_MODIFIED));
+        assert!(!file.headers().contains_key(header::ETAG));
+    }
+
+    #[tokio::test]
+    async fn a_matching_etag_is_not_modified() {
+        let page = get_with(app("a"), "/page", &[]).await;
+        let etag = page.headers()[header::ETAG].to_str().unwrap().to_string();
+        let strong = etag.trim_start_matches("W/").to_string();
+
+        let weak = get_with(app("a"), "/page", &[(header::IF_NONE_MATCH, &etag)]).await;
+        let strong = get_with(app("a"), "/page", &[(header::IF_NONE_MATCH, &strong)]).await;
+        let other_build = get_with(app("b"), "/page", &[(header::IF_NONE_MATCH, &etag)]).await;
+
+        assert_eq!(weak.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(strong.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(other_build.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn if_modified_since_only_applies_to_content() {
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+        let page = get_with(app("a"), "/page", &[(header::IF_MODIFIED_SINCE, &later)]).await;
+        let file = get_with(app("a"), "/file.txt", &[(header::IF_MODIFIED_SINCE, &later)]).await;
+        let old = get_with(app("a"), "/page", &[(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT")]).await;
+
+        assert_eq!(page.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(file.status(), StatusCode::OK);
+        assert_eq!(old.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn any_etag_only_matches_content() {
+        let any = [(header::IF_NONE_MATCH, "*")];
+        let page = get_with(app("a"), "/page", &any).await;
+        let file = get_with(app("a"), "/file.txt", &any).await;
+        let missing = get_with(app("a"), "/missing", &any).await;
+
+        assert_eq!(page.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(file.status(), StatusCode::OK);
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn etags_follow_the_files() {
+        let build = Build("a".into());
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let tag = etag(&build, (3, modified), "/page");
+        assert_eq!(tag, etag(&build, (3, modified), "/page"));
+        assert_ne!(tag, etag(&build, (4, modified), "/page"));
+        assert_ne!(tag, etag(&build, (3, modified + Duration::from_millis(1)), "/page"));
+        assert_ne!(tag, etag(&build, (3, modified), "/other"));
+    }
+}
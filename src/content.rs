@@ -0,0 +1,433 @@
+use crate::app::PostMeta;
+use crate::render::{excerpt, plain_text, prose_text};
+use crate::search::{SearchDocument, SearchIndex};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+
+pub const POSTS_DIR: &str = "posts";
+
+const WORDS_PER_MINUTE: usize = 200;
+
+// How long a loaded index is used before looking for changes again.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct ContentConfig {
+    pub dir: PathBuf,
+    // Whether posts with `draft: true` are published.
+    pub drafts: bool,
+    // Public URL of the site, like `https://blog.example.com`, needed where
+    // links must be absolute.
+    pub site_url: Option<String>,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        ContentConfig {
+            dir: PathBuf::from(POSTS_DIR),
+            drafts: false,
+            site_url: None,
+        }
+    }
+}
+
+static CONFIG: OnceLock<ContentConfig> = OnceLock::new();
+
+// Set once at startup from the command line, before the index is first loaded.
+pub fn configure(config: ContentConfig) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn config() -> &'static ContentConfig {
+    CONFIG.get_or_init(ContentConfig::default)
+}
+
+#[derive(Debug, Error)]
+pub enum ContentError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid metadata in {path}: {source}")]
+    Meta {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+    #[error("no metadata file in {0}")]
+    MissingMeta(PathBuf),
+    #[error("post not found: {0}")]
+    NotFound(String),
+}
+
+// A post as read from its directory: the metadata from the `.yml` and the
+// Markdown source of the file it points to.
+#[derive(Debug, Clone)]
+pub struct Post {
+    pub meta: PostMeta,
+    pub source: String,
+    pub dir: PathBuf,
+}
+
+// Characters escaped in URLs built from file names, including those that
+// would break a `srcset`.
+pub const URL_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b',')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+// Every other file in a post directory is served under this URL, so posts
+// can link to images and downloads next to them with relative links.
+pub fn assets_url(archivo: &str) -> String {
+    format!("/post/{archivo}/assets/")
+}
+
+// `url` as an absolute URL, if it already is one or the site URL is set.
+pub fn absolute_url(url: &str) -> Option<String> {
+    if url.contains("://") {
+        return Some(url.to_string());
+    }
+    let site_url = config().site_url.as_deref()?;
+    Some(format!("{}/{}", site_url.trim_end_matches('/'), url.trim_start_matches('/')))
+}
+
+impl Post {
+    fn is_asset(&self, path: &Path) -> bool {
+        path.is_file()
+            && path.extension().is_none_or(|ext| ext != "yml")
+            && path != self.dir.join(&self.meta.archivo)
+    }
+
+    // The file for `path` relative to the post directory, if it is an asset.
+    pub fn asset(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path);
+        if !relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            return None;
+        }
+        let path = self.dir.join(relative);
+        self.is_asset(&path).then_some(path)
+    }
+
+    // Paths of all assets, relative to the post directory.
+    pub fn assets(&self) -> Vec<PathBuf> {
+        fn visit(post: &Post, dir: &Path, assets: &mut Vec<PathBuf>) {
+            let Ok(entries) = fs::read_dir(dir) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    visit(post, &path, assets);
+                } else if post.is_asset(&path) {
+                    if let Ok(relative) = path.strip_prefix(&post.dir) {
+                        assets.push(relative.to_path_buf());
+                    }
+                }
+            }
+        }
+
+        let mut assets = Vec::new();
+        visit(self, &self.dir, &mut assets);
+        assets.sort();
+        assets
+    }
+}
+
+fn read(path: &Path) -> Result<String, ContentError> {
+    fs::read_to_string(path).map_err(|source| ContentError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+pub fn word_count(source: &str) -> usize {
+    prose_text(source).split_whitespace().count()
+}
+
+pub fn reading_time(words: usize) -> usize {
+    words.div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+// The metadata file of the post in `dir`. With more than one `.yml`, the
+// first by name, whatever order the directory is listed in.
+pub fn meta_path(dir: &Path) -> Result<PathBuf, ContentError> {
+    let entries = fs::read_dir(dir).map_err(|source| ContentError::Io {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+
+    let mut meta_paths = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|source| ContentError::Io {
+                path: dir.to_path_buf(),
+                source,
+            })?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "yml") {
+            meta_paths.push(path);
+        }
+    }
+    meta_paths
+        .into_iter()
+        .min()
+        .ok_or_else(|| ContentError::MissingMeta(dir.to_path_buf()))
+}
+
+pub fn load_post_dir(dir: &Path) -> Result<Post, ContentError> {
+    let meta_path = meta_path(dir)?;
+    let mut meta: PostMeta =
+        serde_yaml::from_str(&read(&meta_path)?).map_err(|source| ContentError::Meta {
+            path: meta_path.clone(),
+            source,
+        })?;
+    let source = read(&dir.join(&meta.archivo))?;
+
+    if let Some(portada) = &mut meta.portada {
+        if !portada.starts_with('/') && !portada.contains("://") {
+            let path = utf8_percent_encode(portada.trim_start_matches("./"), URL_PATH);
+            *portada = format!("{}{path}", assets_url(&meta.archivo));
+        }
+    }
+
+    if meta.descripcion.trim().is_empty() {
+        meta.descripcion = excerpt(&source);
+    }
+    meta.palabras = word_count(&source);
+    meta.minutos_lectura = reading_time(meta.palabras);
+
+    Ok(Post {
+        meta,
+        source,
+        dir: dir.to_path_buf(),
+    })
+}
+
+pub fn load_posts(root: &Path) -> Result<Vec<Post>, ContentError> {
+    let entries = fs::read_dir(root).map_err(|source| ContentError::Io {
+        path: root.to_path_buf(),
+        source,
+    })?;
+
+    let mut posts = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|source| ContentError::Io {
+                path: root.to_path_buf(),
+                source,
+            })?
+            .path();
+        if path.is_dir() {
+            posts.push(load_post_dir(&path)?);
+        }
+    }
+    Ok(posts)
+}
+
+// Everything the server needs from the `posts` directory, loaded once and
+// reloaded when a file in it changes. Changes are looked for at most once a
+// second.
+pub struct ContentIndex {
+    pub posts: Vec<Post>,
+    pub search: SearchIndex,
+    // Incremented on every reload.
+    pub version: u64,
+    stamp: Stamp,
+    // When the posts directory was last compared with `stamp`.
+    checked: Mutex<Instant>,
+}
+
+// Number of entries and latest modification time under the posts directory.
+pub type Stamp = (usize, SystemTime);
+
+static INDEX: RwLock<Option<Arc<ContentIndex>>> = RwLock::new(None);
+
+fn stamp(root: &Path) -> Result<Stamp, ContentError> {
+    fn visit(path: &Path, stamp: &mut Stamp) -> std::io::Result<()> {
+        let metadata = fs::metadata(path)?;
+        stamp.0 += 1;
+        stamp.1 = stamp.1.max(metadata.modified()?);
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path)? {
+                visit(&entry?.path(), stamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut stamp = (0, SystemTime::UNIX_EPOCH);
+    visit(root, &mut stamp).map_err(|source| ContentError::Io {
+        path: root.to_path_buf(),
+        source,
+    })?;
+    Ok(stamp)
+}
+
+impl ContentIndex {
+    fn load(root: &Path, stamp: Stamp, version: u64) -> Result<Self, ContentError> {
+        let mut posts = load_posts(root)?;
+        if !config().drafts {
+            posts.retain(|post| !post.meta.draft);
+        }
+        let bodies: Vec<String> = posts.iter().map(|post| plain_text(&post.source)).collect();
+        let search = SearchIndex::build(posts.iter().zip(&bodies).map(|(post, body)| {
+            SearchDocument {
+                archivo: &post.meta.archivo,
+                titulo: &post.meta.titulo,
+                fecha: &post.meta.fecha,
+                etiquetas: &post.meta.etiquetas,
+                descripcion: &post.meta.descripcion,
+                body,
+            }
+        }));
+
+        Ok(ContentIndex {
+            posts,
+            search,
+            version,
+            stamp,
+            checked: Mutex::new(Instant::now()),
+        })
+    }
+
+    // Whether the posts directory was looked at recently. If not, the caller
+    // is the one to look, everyone else keeps using this index meanwhile.
+    fn recently_checked(&self) -> bool {
+        let mut checked = self.checked.lock().unwrap();
+        if checked.elapsed() < RECHECK_INTERVAL {
+            return true;
+        }
+        *checked = Instant::now();
+        false
+    }
+
+    // Latest change to a file under the posts directory.
+    pub fn modified(&self) -> SystemTime {
+        self.stamp.1
+    }
+
+    // Changes with `version`, but comes from the files, so it is the same
+    // after a restart if they are.
+    pub fn stamp(&self) -> Stamp {
+        self.stamp
+    }
+
+    // Posts are addressed by their Markdown file name, `blog_en_rust.md`
+    // lives in `posts/blog_en_rust/`.
+    pub fn post(&self, archivo: &str) -> Result<&Post, ContentError> {
+        self.posts
+            .iter()
+            .find(|post| post.meta.archivo == archivo)
+            .ok_or_else(|| ContentError::NotFound(archivo.to_string()))
+    }
+}
+
+pub fn content_index() -> Result<Arc<ContentIndex>, ContentError> {
+    if let Some(index) = INDEX.read().unwrap().as_ref() {
+        if index.recently_checked() {
+            return Ok(index.clone());
+        }
+    }
+
+    let root = config().dir.as_path();
+    let stamp = stamp(root)?;
+    if let Some(index) = INDEX.read().unwrap().as_ref() {
+        if index.stamp == stamp {
+            return Ok(index.clone());
+        }
+    }
+
+    let mut current = INDEX.write().unwrap();
+    if let Some(index) = current.as_ref() {
+        if index.stamp == stamp {
+            return Ok(index.clone());
+        }
+    }
+    let version = current.as_ref().map_or(1, |index| index.version + 1);
+    let index = Arc::new(ContentIndex::load(root, stamp, version)?);
+    *current = Some(index.clone());
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_words_without_markup_or_code() {
+        let source = "# Hola mundo\n\nUn *párrafo* con [un enlace](/x) y `código`.\n\n```rust\nfn main() { println!(\"no cuenta\"); }\n```\n\n:::note\nDentro\n:::\n";
+        assert_eq!(word_count(source), 10);
+    }
+
+    #[test]
+    fn loads_the_first_metadata_file_by_name() {
+        let dir = std::env::temp_dir().join(format!("blog-content-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, titulo) in [("b.yml", "B"), ("a.yml", "A"), ("c.yml", "C")] {
+            let meta = format!("titulo: {titulo}\nfecha: 2024-01-01\narchivo: post.md\n");
+            fs::write(dir.join(name), meta).unwrap();
+        }
+        fs::write(dir.join("post.md"), "Sin descripción en el `.yml`.\n").unwrap();
+
+        let post = load_post_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(post.meta.titulo, "A");
+        assert_eq!(post.meta.descripcion, "Sin descripción en el .yml.");
+        assert_eq!(post.meta.palabras, 5);
+    }
+
+    #[test]
+    fn assets_are_the_other_files_of_the_post() {
+        let dir = std::env::temp_dir().join(format!("blog-assets-{}", std::process::id()));
+        fs::create_dir_all(dir.join("img")).unwrap();
+        for file in ["post.md", "post.yml", "foto.png", "img/b.jpg", "notas.txt"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let post = Post {
+            meta: serde_yaml::from_str("titulo: T\nfecha: 01/01/2024\narchivo: post.md\n").unwrap(),
+            source: String::new(),
+            dir: dir.clone(),
+        };
+
+        let assets = post.assets();
+        let found = |path: &str| post.asset(path).is_some();
+        let (png, jpg, md, yml, up, dir_itself) = (
+            found("foto.png"),
+            found("img/b.jpg"),
+            found("post.md"),
+            found("post.yml"),
+            found("../post/foto.png"),
+            found("img"),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(assets, [PathBuf::from("foto.png"), PathBuf::from("img/b.jpg"), PathBuf::from("notas.txt")]);
+        assert!(png && jpg);
+        assert!(!md && !yml && !up && !dir_itself);
+    }
+
+    #[test]
+    fn asset_urls_are_under_the_post() {
+        assert_eq!(assets_url("hola.md"), "/post/hola.md/assets/");
+    }
+
+    #[test]
+    fn reading_time_rounds_up_to_a_minute() {
+        assert_eq!(reading_time(0), 1);
+        assert_eq!(reading_time(1), 1);
+        assert_eq!(reading_time(200), 1);
+        assert_eq!(reading_time(201), 2);
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, HashMap};
+
+// Directives let posts insert callouts, figures and embeds without raw HTML:
+//
+//     :::note[Título opcional]
+//     Contenido **markdown** de la nota.
+//     :::
+//
+//     ::youtube{id=dQw4w9WgXcQ}
+//
+// Containers use three or more colons and are closed by a line with only
+// colons, leaf directives use two colons and take a whole line.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectiveKind {
+    Leaf,
+    Container,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub name: String,
+    pub kind: DirectiveKind,
+    pub label: Option<String>,
+    pub attrs: BTreeMap<String, String>,
+}
+
+impl Directive {
+    pub fn attr(&self, key: &str) -> Option<&str> {
+        self.attrs.get(key).map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Marker {
+    Open(Directive),
+    Close,
+    Leaf(Directive),
+}
+
+// Receives the directive and, for containers, the already rendered HTML of
+// its content. Leaf directives get an empty string.
+pub type DirectiveHandler = fn(&Directive, &str) -> String;
+
+#[derive(Clone)]
+pub struct Directives {
+    handlers: HashMap<String, DirectiveHandler>,
+}
+
+impl Directives {
+    pub fn empty() -> Self {
+        Directives {
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, handler: DirectiveHandler) -> &mut Self {
+        self.handlers.insert(name.to_string(), handler);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<DirectiveHandler> {
+        self.handlers.get(name).copied()
+    }
+}
+
+impl Default for Directives {
+    fn default() -> Self {
+        let mut directives = Directives::empty();
+        directives
+            .register("note", note)
+            .register("warning", warning)
+            .register("figure", figure)
+            .register("youtube", youtube)
+            .register("gist", gist)
+            .register("island", crate::islands::island_directive);
+        directives
+    }
+}
+
+pub fn parse_marker(line: &str) -> Option<Marker> {
+    let line = line.trim();
+    let colons = line.chars().take_while(|&c| c == ':').count();
+    if colons < 2 {
+        return None;
+    }
+
+    let rest = &line[colons..];
+    if rest.is_empty() {
+        return (colons >= 3).then_some(Marker::Close);
+    }
+
+    let name_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .unwrap_or(rest.len());
+    let name = &rest[..name_len];
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut rest = &rest[name_len..];
+    let mut label = None;
+    if let Some(after) = rest.strip_prefix('[') {
+        let end = after.find(']')?;
+        label = Some(after[..end].to_string());
+        rest = &after[end + 1..];
+    }
+
+    let mut attrs = BTreeMap::new();
+    if let Some(after) = rest.strip_prefix('{') {
+        let end = after.rfind('}')?;
+        attrs = parse_attrs(&after[..end])?;
+        rest = &after[end + 1..];
+    }
+
+    if !rest.trim().is_empty() {
+        return None;
+    }
+
+    let kind = if colons >= 3 {
+        DirectiveKind::Container
+    } else {
+        DirectiveKind::Leaf
+    };
+    let directive = Directive {
+        name: name.to_string(),
+        kind,
+        label,
+        attrs,
+    };
+    Some(match kind {
+        DirectiveKind::Container => Marker::Open(directive),
+        DirectiveKind::Leaf => Marker::Leaf(directive),
+    })
+}
+
+// Parses `id=abc titulo="Hola mundo" .clase #ancla activo`.
+fn parse_attrs(source: &str) -> Option<BTreeMap<String, String>> {
+    let mut attrs = BTreeMap::new();
+    let mut chars = source.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+
+        let shorthand = match first {
+            '.' => Some("class"),
+            '#' => Some("id"),
+            _ => None,
+        };
+        if shorthand.is_some() {
+            chars.next();
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        if key.is_empty() {
+            return None;
+        }
+
+        if let Some(attr) = shorthand {
+            let entry = attrs.entry(attr.to_string()).or_insert_with(String::new);
+            if !entry.is_empty() {
+                entry.push(' ');
+            }
+            entry.push_str(&key);
+            continue;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            match chars.next_if(|c| *c == '"' || *c == '\'') {
+                Some(quote) => loop {
+                    match chars.next() {
+                        Some(c) if c == quote => break,
+                        Some(c) => value.push(c),
+                        None => return None,
+                    }
+                },
+                None => {
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                        value.push(c);
+                    }
+                }
+            }
+        }
+        attrs.insert(key, value);
+    }
+
+    Some(attrs)
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn callout(directive: &Directive, content: &str, class: &str, title: &str) -> String {
+    let title = directive.label.as_deref().unwrap_or(title);
+    format!(
+        "<aside class=\"callout callout-{class}\"><p class=\"callout-title\">{}</p>\n{content}</aside>\n",
+        escape_html(title)
+    )
+}
+
+fn note(directive: &Directive, content: &str) -> String {
+    callout(directive, content, "note", "Nota")
+}
+
+fn warning(directive: &Directive, content: &str) -> String {
+    callout(directive, content, "warning", "Advertencia")
+}
+
+fn figure(directive: &Directive, _content: &str) -> String {
+    let Some(src) = directive.attr("src") else {
+        return String::new();
+    };
+    let alt = directive.attr("alt").or(directive.label.as_deref()).unwrap_or_default();
+    let caption = directive
+        .label
+        .as_deref()
+        .map(|label| format!("<figcaption>{}</figcaption>", escape_html(label)))
+        .unwrap_or_default();
+    format!(
+        "<figure><img src=\"{}\" alt=\"{}\" />{caption}</figure>\n",
+        escape_html(src),
+        escape_html(alt)
+    )
+}
+
+fn is_embed_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '/')
+}
+
+fn youtube(directive: &Directive, _content: &str) -> String {
+    match directive.attr("id") {
+        Some(id) if is_embed_id(id) => {
+            let title = directive.label.as_deref().unwrap_or("Video de YouTube");
+            format!(
+                "<div class=\"embed\"><iframe src=\"https://www.youtube-nocookie.com/embed/{id}\" title=\"{}\" loading=\"lazy\" allowfullscreen></iframe></div>\n",
+                escape_html(title)
+            )
+        }
+        _ => String::new(),
+    }
+}
+
+fn gist(directive: &Directive, _content: &str) -> String {
+    match directive.attr("id") {
+        Some(id) if is_embed_id(id) => format!(
+            "<div class=\"embed embed-gist\"><iframe src=\"https://gist.github.com/{id}.pibb\" title=\"Gist {id}\" loading=\"lazy\"></iframe></div>\n"
+        ),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attrs(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_containers_with_label_and_attrs() {
+        let Some(Marker::Open(directive)) = parse_marker(":::note[Título opcional]{.destacada}") else {
+            panic!("not an opener");
+        };
+        assert_eq!(directive.name, "note");
+        assert_eq!(directive.kind, DirectiveKind::Container);
+        assert_eq!(directive.label.as_deref(), Some("Título opcional"));
+        assert_eq!(directive.attrs, attrs(&[("class", "destacada")]));
+    }
+
+    #[test]
+    fn parses_leaves_and_closers() {
+        let Some(Marker::Leaf(directive)) = parse_marker("  ::youtube{id=dQw4w9WgXcQ}  ") else {
+            panic!("not a leaf");
+        };
+        assert_eq!(directive.attr("id"), Some("dQw4w9WgXcQ"));
+        assert_eq!(directive.label, None);
+
+        assert_eq!(parse_marker(":::"), Some(Marker::Close));
+        assert_eq!(parse_marker("::::::"), Some(Marker::Close));
+        assert_eq!(parse_marker("::"), None);
+    }
+
+    #[test]
+    fn rejects_lines_that_are_not_markers() {
+        assert_eq!(parse_marker(":note"), None);
+        assert_eq!(parse_marker("::1note"), None);
+        assert_eq!(parse_marker(":::note[sin cerrar"), None);
+        assert_eq!(parse_marker(":::note{id=a"), None);
+        assert_eq!(parse_marker(":::note y texto"), None);
+        assert_eq!(parse_marker("Hola ::note"), None);
+    }
+
+    #[test]
+    fn parses_attrs() {
+        assert_eq!(
+            parse_attrs(r#"id=abc titulo="Hola mundo" alt='a b' activo"#),
+            Some(attrs(&[("id", "abc"), ("titulo", "Hola mundo"), ("alt", "a b"), ("activo", "")]))
+        );
+        assert_eq!(
+            parse_attrs(".una .otra #ancla"),
+            Some(attrs(&[("class", "una otra"), ("id", "ancla")]))
+        );
+        assert_eq!(parse_attrs("   "), Some(BTreeMap::new()));
+    }
+
+    #[test]
+    fn rejects_broken_attrs() {
+        assert_eq!(parse_attrs(r#"titulo="sin cerrar"#), None);
+        assert_eq!(parse_attrs("=valor"), None);
+        assert_eq!(parse_attrs(". #"), None);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::app::App;
+use crate::assets::ASSETS;
+use crate::compress::{precompress, CompressError};
+use crate::content::{assets_url, config, content_index, ContentError};
+use crate::directives::escape_html;
+use crate::images::{images_url, variant, variant_path, variants, ImageError};
+use crate::og::{og_image, og_url, OgError};
+use crate::search::SEARCH_INDEX_PATH;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use leptos::LeptosOptions;
+use leptos_axum::{generate_route_list, render_app_async};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tower::ServiceExt;
+
+// Marks exported pages so the client leaves navigation to the browser, there
+// are no server functions behind a static file server.
+pub const STATIC_MARKER: &str = r#"<meta name="blog-static" content="true"/>"#;
+// Added to the requests for the pages being exported, see `is_static_site`.
+// An extension rather than a header, which any client could send.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticSite;
+
+// Routes served by axum outside of Leptos that are part of the site.
+const EXTRA_PATHS: &[&str] = &[SEARCH_INDEX_PATH];
+
+// Routes of `App` that are served but not part of the exported site.
+const UNLISTED_PATHS: &[&str] = &["/secret"];
+
+pub const SITEMAP_PATH: &str = "/sitemap.xml";
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("could not write {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Content(#[from] ContentError),
+    #[error(transparent)]
+    Image(#[from] ImageError),
+    #[error(transparent)]
+    Og(#[from] OgError),
+    #[error(transparent)]
+    Compress(#[from] CompressError),
+    #[error("{path} answered with {status}")]
+    Status { path: String, status: StatusCode },
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> ExportError + '_ {
+    move |source| ExportError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+// Paths of the routes in `App`, with parameters like `/post/:archivo` left in.
+pub fn route_paths() -> Vec<String> {
+    generate_route_list(App)
+        .iter()
+        .map(|route| match route.path() {
+            "" => "/".to_string(),
+            path => path.to_string(),
+        })
+        .collect()
+}
+
+fn exported(path: &str) -> bool {
+    !path.contains(':') && !path.contains('*') && !UNLISTED_PATHS.contains(&path)
+}
+
+// Every page of the site: routes without parameters as they are and post
+// routes once per post.
+pub fn site_paths() -> Result<Vec<String>, ContentError> {
+    let index = content_index()?;
+    let mut paths = Vec::new();
+
+    for path in route_paths() {
+        if path.contains(":archivo") {
+            for post in &index.posts {
+                paths.push(path.replace(":archivo", &post.meta.archivo));
+            }
+        } else if exported(&path) {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+// Every page in the sitemaps.org format, which wants absolute URLs.
+pub fn sitemap(site_url: &str, paths: &[String]) -> String {
+    let site_url = site_url.trim_end_matches('/');
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for path in paths {
+        xml.push_str(&format!("  <url><loc>{}</loc></url>\n", escape_html(&format!("{site_url}{path}"))));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn page_file(out_dir: &Path, path: &str) -> PathBuf {
+    out_dir.join(path.trim_start_matches('/')).join("index.html")
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<(), ExportError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error(parent))?;
+    }
+    fs::write(path, contents).map_err(io_error(path))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), ExportError> {
+    fs::create_dir_all(to).map_err(io_error(to))?;
+    for entry in fs::read_dir(from).map_err(io_error(from))? {
+        let entry = entry.map_err(io_error(from))?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target).map_err(io_error(&target))?;
+        }
+    }
+    Ok(())
+}
+
+async fn render_page(options: &LeptosOptions, path: &str) -> (StatusCode, String) {
+    let handler = render_app_async(options.clone(), App);
+    let request = Request::get(path)
+        .extension(StaticSite)
+        .body(Body::empty())
+        .unwrap();
+    let response = handler(request).await;
+    let status = response.status();
+    let html = response
+        .into_body()
+        .replacen("</head>", &format!("{STATIC_MARKER}</head>"), 1);
+    (status, html)
+}
+
+// Renders the whole site to `out_dir`, together with the compiled assets in
+// the site root. Returns the number of pages written.
+pub async fn export(options: &LeptosOptions, out_dir: &Path) -> Result<usize, ExportError> {
+    let site_root = Path::new(&options.site_root);
+    if site_root.is_dir() {
+        copy_dir(site_root, out_dir)?;
+        for (original, fingerprinted) in ASSETS {
+            let file = out_dir.join(original.trim_start_matches('/'));
+            if file.is_file() {
+                let target = out_dir.join(fingerprinted.trim_start_matches('/'));
+                fs::copy(&file, &target).map_err(io_error(&target))?;
+            }
+        }
+    } else {
+        leptos::logging::warn!(
+            "{} does not exist, run `cargo leptos build` first to include the assets",
+            site_root.display()
+        );
+    }
+
+    let paths = site_paths()?;
+    for path in &paths {
+        let (status, html) = render_page(options, path).await;
+        if status != StatusCode::OK {
+            return Err(ExportError::Status {
+                path: path.clone(),
+                status,
+            });
+        }
+        write(&page_file(out_dir, path), html.as_bytes())?;
+    }
+
+    for post in &content_index()?.posts {
+        let assets = out_dir.join(assets_url(&post.meta.archivo).trim_start_matches('/'));
+        for asset in post.assets() {
+            let target = assets.join(&asset);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(io_error(parent))?;
+            }
+            fs::copy(post.dir.join(&asset), &target).map_err(io_error(&target))?;
+        }
+
+        let images = out_dir.join(images_url(&post.meta.archivo).trim_start_matches('/'));
+        for (path, width, format) in variants(post) {
+            let file = variant(post, &path, width, format)?;
+            let target = images.join(variant_path(width, &path, format));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(io_error(parent))?;
+            }
+            fs::copy(&file, &target).map_err(io_error(&target))?;
+        }
+
+        if post.meta.portada.is_none() {
+            let card = og_image(post)?;
+            let target = out_dir.join(og_url(&post.meta.archivo).trim_start_matches('/'));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(io_error(parent))?;
+            }
+            fs::copy(&card, &target).map_err(io_error(&target))?;
+        }
+    }
+
+    match &config().site_url {
+        Some(site_url) => write(
+            &out_dir.join(SITEMAP_PATH.trim_start_matches('/')),
+            sitemap(site_url, &paths).as_bytes(),
+        )?,
+        None => leptos::logging::warn!("no --site-url, the export has no {SITEMAP_PATH}"),
+    }
+
+    let (_, not_found) = render_page(options, "/404").await;
+    write(&out_dir.join("404.html"), not_found.as_bytes())?;
+
+    let router = crate::server::router(options.clone(), None);
+    for path in EXTRA_PATHS {
+        let request = Request::get(*path).body(Body::empty()).unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        if response.status() != StatusCode::OK {
+            return Err(ExportError::Status {
+                path: path.to_string(),
+                status: response.status(),
+            });
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|err| ExportError::Io {
+                path: PathBuf::from(path),
+                source: std::io::Error::other(err),
+            })?;
+        write(&out_dir.join(path.trim_start_matches('/')), &body)?;
+    }
+
+    precompress(out_dir)?;
+    Ok(paths.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sitemap_lists_absolute_urls() {
+        let paths = ["/".to_string(), "/post/a&b.md".to_string()];
+        let xml = sitemap("https://blog.example.com/", &paths);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset "));
+        assert!(xml.contains("<url><loc>https://blog.example.com/</loc></url>"));
+        assert!(xml.contains("<url><loc>https://blog.example.com/post/a&amp;b.md</loc></url>"));
+        assert!(xml.ends_with("</urlset>\n"));
+    }
+
+    #[test]
+    fn site_paths_come_from_the_routes() {
+        let routes = route_paths();
+        for path in ["/", "/about", "/projects", "/search", "/post/:archivo"] {
+            assert!(routes.iter().any(|route| route == path), "{path} in {routes:?}");
+        }
+    }
+
+    #[test]
+    fn unlisted_routes_are_not_exported() {
+        let routes = route_paths();
+        assert!(routes.iter().any(|route| route == "/secret"));
+        assert!(!exported("/secret"));
+        assert!(!exported("/post/:archivo"));
+        assert!(exported("/about"));
+    }
+}
@@ -1,14 +1,15 @@
 use crate::app::App;
+use crate::assets::{asset_path, IMMUTABLE, SHORT};
 use axum::response::Response as AxumResponse;
 use axum::{
     body::Body,
-    extract::State,
-    http::{Request, Response, StatusCode},
+    extract::{Path, State},
+    http::{header, HeaderValue, Request, Response, StatusCode},
     response::IntoResponse,
 };
 use leptos::*;
 use tower::ServiceExt;
-use tower_http::services::ServeDir;
+use tower_http::services::{ServeDir, ServeFile};
 
 pub async fn file_and_error_handler(
     State(options): State<LeptosOptions>,
@@ -25,12 +26,29 @@ pub async fn file_and_error_handler(
             .insert("accept-encoding", encodings.clone());
     }
 
+    // Fingerprinted files are served from their real name, and so are the
+    // JS, WASM and CSS in the pkg dir when cargo-leptos hashed their names.
+    let path = parts.uri.path();
+    let cache_control = if let Some(original) = asset_path(path) {
+        if let Ok(uri) = original.parse() {
+            static_parts.uri = uri;
+        }
+        IMMUTABLE
+    } else if options.hash_files && path.starts_with(&format!("/{}/", options.site_pkg_dir)) {
+        IMMUTABLE
+    } else {
+        SHORT
+    };
+
     let res = get_static_file(Request::from_parts(static_parts, Body::empty()), &root)
         .await
         .unwrap();
 
     if res.status() == StatusCode::OK {
-        res.into_response()
+        let mut res = res.into_response();
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+        res
     } else {
         let handler = leptos_axum::render_app_to_stream(options.to_owned(), App);
         handler(Request::from_parts(parts, body))
@@ -48,6 +66,7 @@ async fn get_static_file(
     match ServeDir::new(root)
         .precompressed_gzip()
         .precompressed_br()
+        .precompressed_zstd()
         .oneshot(request)
         .await
     {
@@ -58,3 +77,127 @@ async fn get_static_file(
         )),
     }
 }
+
+pub async fn search_index_handler() -> AxumResponse {
+    match crate::content::content_index() {
+        Ok(index) => axum::Json(&index.search).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+// Files next to a post, see `crate::content::assets_url`.
+pub async fn post_asset_handler(
+    Path((archivo, path)): Path<(String, String)>,
+    req: Request<Body>,
+) -> AxumResponse {
+    let index = match crate::content::content_index() {
+        Ok(index) => index,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let Some(file) = index.post(&archivo).ok().and_then(|post| post.asset(&path)) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    serve_file(file, req).await
+}
+
+// Resized and converted copies of the images next to a post, see `crate::images`.
+pub async fn image_variant_handler(
+    Path((archivo, width, path)): Path<(String, u32, String)>,
+    req: Request<Body>,
+) -> AxumResponse {
+    let index = match crate::content::content_index() {
+        Ok(index) => index,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let variant = tokio::task::spawn_blocking(move || {
+        let post = index.post(&archivo).ok()?;
+        let (asset, format) = crate::images::parse_variant(post, &path)?;
+        Some(crate::images::variant(post, asset, width, format))
+    })
+    .await;
+
+    let file = match variant {
+        Ok(Some(Ok(file))) => file,
+        Ok(None) | Ok(Some(Err(crate::images::ImageError::NotFound(_)))) => {
+            return StatusCode::NOT_FOUND.into_response()
+        }
+        Ok(Some(Err(err))) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    serve_file(file, req).await
+}
+
+// Social card of a post, see `crate::og`.
+pub async fn og_image_handler(Path(file): Path<String>, req: Request<Body>) -> AxumResponse {
+    let Some(archivo) = file.strip_suffix(".png").map(str::to_string) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let index = match crate::content::content_index() {
+        Ok(index) => index,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let image = tokio::task::spawn_blocking(move || index.post(&archivo).ok().map(crate::og::og_image)).await;
+
+    let file = match image {
+        Ok(Some(Ok(file))) => file,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Ok(Some(Err(err))) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    serve_file(file, req).await
+}
+
+// Files next to posts keep their URL when they change, so they are only
+// cached for a while.
+async fn serve_file(file: std::path::PathBuf, req: Request<Body>) -> AxumResponse {
+    match ServeFile::new(file).oneshot(req).await {
+        Ok(res) => {
+            let mut res = res.into_response();
+            res.headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static(SHORT));
+            res
+        }
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Error serving files: {err}"),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn cache_control(options: &LeptosOptions, path: &str) -> Option<String> {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let response = file_and_error_handler(State(options.clone()), request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn only_files_named_after_their_contents_are_immutable() {
+        let root = std::env::temp_dir().join(format!("blog-fileserv-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("pkg")).unwrap();
+        std::fs::write(root.join("favicon.ico"), "ico").unwrap();
+        std::fs::write(root.join("robots.txt"), "").unwrap();
+        std::fs::write(root.join("pkg/blog.0123.css"), "").unwrap();
+        let options = LeptosOptions::builder()
+            .site_root(root.to_str().unwrap())
+            .hash_files(true)
+            .build();
+
+        let fingerprinted = cache_control(&options, crate::assets::asset_url("/favicon.ico")).await;
+        let plain = cache_control(&options, "/robots.txt").await;
+        let pkg = cache_control(&options, "/pkg/blog.0123.css").await;
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(fingerprinted.as_deref(), Some(IMMUTABLE));
+        assert_eq!(plain.as_deref(), Some(SHORT));
+        assert_eq!(pkg.as_deref(), Some(IMMUTABLE));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::content::content_index;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::DateTime;
+use serde_json::json;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use thiserror::Error;
+
+// Endpoints for the container orchestrator and for whoever wonders what is
+// deployed.
+
+pub const GIT_SHA: &str = env!("BLOG_GIT_SHA");
+
+pub const READY_PATH: &str = "/readyz";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// RFC 3339, from the seconds build.rs saw.
+pub fn build_time() -> String {
+    env!("BLOG_BUILD_TIME")
+        .parse()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+// The process is up and answering.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+// The posts can be served: the content index loads without errors. Those
+// errors name files on the server, they only go to the log.
+async fn readyz() -> Response {
+    match content_index() {
+        Ok(index) => Json(json!({ "status": "ready", "posts": index.posts.len() })).into_response(),
+        Err(err) => {
+            leptos::logging::error!("not ready: {err}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "unavailable", "error": "the posts could not be loaded" })),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn version() -> Json<serde_json::Value> {
+    let posts = content_index().ok().map(|index| index.posts.len());
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": GIT_SHA,
+        "build_time": build_time(),
+        "posts": posts,
+    }))
+}
+
+pub fn health_routes() -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route(READY_PATH, get(readyz))
+        .route("/version", get(version))
+}
+
+#[derive(Debug, Error)]
+pub enum HealthError {
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+    #[error("not ready: {0}")]
+    Status(reqwest::StatusCode),
+}
+
+// Asks a server on this machine whether it is ready, for container health
+// checks, which have no curl to do it. A server listening on every address
+// is reached through the loopback one.
+pub async fn check_health(addr: SocketAddr, https: bool) -> Result<(), HealthError> {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    let scheme = if https { "https" } else { "http" };
+    let url = format!("{scheme}://{}{READY_PATH}", SocketAddr::new(ip, addr.port()));
+    // The certificate is for the public name, not for the address it is
+    // reached at here.
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .danger_accept_invalid_certs(true)
+        .build()?;
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(HealthError::Status(response.status()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn serve(app: Router) -> SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn a_ready_server_is_healthy() {
+        let addr = serve(health_routes()).await;
+        let every_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), addr.port());
+        assert!(check_health(addr, false).await.is_ok());
+        assert!(check_health(every_address, false).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_server_that_isnt_ready_is_not() {
+        let unavailable = Router::new().route(READY_PATH, get(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+        let addr = serve(unavailable).await;
+        assert!(matches!(
+            check_health(addr, false).await,
+            Err(HealthError::Status(StatusCode::SERVICE_UNAVAILABLE))
+        ));
+    }
+
+    #[tokio::test]
+    async fn nothing_listening_is_not_healthy() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert!(matches!(check_health(addr, false).await, Err(HealthError::Request(_))));
+    }
+}
@@ -0,0 +1,514 @@
+use crate::content::{assets_url, Post, URL_PATH};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageReader};
+use lol_html::html_content::ContentType;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+use thiserror::Error;
+
+// Images next to a post are served in several widths and formats, generated
+// on the first request (or by the export) and kept in this directory:
+//
+//     /post/hola.md/assets/foto.png            the original
+//     /post/hola.md/images/480/foto.png        resized, same format
+//     /post/hola.md/images/480/foto.png.webp   resized, WebP
+
+pub const IMAGE_CACHE_DIR: &str = ".cache/images";
+
+const WIDTHS: &[u32] = &[480, 960, 1440];
+// Posts are at most `max-w-3xl` wide, minus the padding.
+const SIZES: &str = "(max-width: 48rem) calc(100vw - 2rem), 46rem";
+const JPEG_QUALITY: u8 = 80;
+const WEBP_QUALITY: f32 = 80.0;
+#[cfg(feature = "avif")]
+const AVIF_QUALITY: u8 = 70;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("no image {0}")]
+    NotFound(String),
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // Whatever the original is, PNG or JPEG.
+    Original,
+    WebP,
+    #[cfg(feature = "avif")]
+    Avif,
+}
+
+impl Format {
+    // Best first, as they go in `<picture>`.
+    const MODERN: &'static [Format] = &[
+        #[cfg(feature = "avif")]
+        Format::Avif,
+        Format::WebP,
+    ];
+
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Format::Original => None,
+            Format::WebP => Some("webp"),
+            #[cfg(feature = "avif")]
+            Format::Avif => Some("avif"),
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            Format::Original => "",
+            Format::WebP => "image/webp",
+            #[cfg(feature = "avif")]
+            Format::Avif => "image/avif",
+        }
+    }
+
+    fn all() -> impl Iterator<Item = Format> {
+        std::iter::once(Format::Original).chain(Format::MODERN.iter().copied())
+    }
+}
+
+pub fn images_url(archivo: &str) -> String {
+    format!("/post/{archivo}/images/")
+}
+
+fn is_raster(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "png" | "jpg" | "jpeg" | "webp"))
+}
+
+fn is_webp(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("webp"))
+}
+
+// Smaller widths than the original, and the original width itself.
+fn widths(original: u32) -> Vec<u32> {
+    WIDTHS
+        .iter()
+        .copied()
+        .filter(|&width| width < original)
+        .chain([original])
+        .collect()
+}
+
+fn formats(source: &Path) -> impl Iterator<Item = Format> {
+    let webp = is_webp(source);
+    Format::all().filter(move |&format| !(webp && format == Format::WebP))
+}
+
+pub fn variant_path(width: u32, path: &str, format: Format) -> String {
+    match format.extension() {
+        Some(ext) => format!("{width}/{path}.{ext}"),
+        None => format!("{width}/{path}"),
+    }
+}
+
+// Splits `foto.png.webp` into the asset and the format it is wanted in.
+pub fn parse_variant<'a>(post: &Post, path: &'a str) -> Option<(&'a str, Format)> {
+    for &format in Format::MODERN {
+        let ext = format.extension().unwrap_or_default();
+        if let Some(asset) = path.strip_suffix(ext).and_then(|path| path.strip_suffix('.')) {
+            if post.asset(asset).is_some() {
+                return Some((asset, format));
+            }
+        }
+    }
+    post.asset(path).map(|_| (path, Format::Original))
+}
+
+// A file next to `target` to write it in and then rename, so nobody ever
+// sees half of it. Its name is different for every call, two requests
+// generating the same file at once don't write into each other's.
+pub fn partial_path(target: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(format!(
+        ".{}-{}.partial",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    PathBuf::from(partial)
+}
+
+// The encoder in `image` only writes lossless WebP, often bigger than the
+// JPEG it replaces.
+fn webp(image: &DynamicImage) -> Vec<u8> {
+    let rgba = image.to_rgba8();
+    webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+        .encode(WEBP_QUALITY)
+        .to_vec()
+}
+
+fn encode(image: &DynamicImage, source: &Path, format: Format, target: &Path) -> Result<(), ImageError> {
+    let io_error = |source| ImageError::Io {
+        path: target.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+
+    let partial = partial_path(target);
+    let written = File::create(&partial).map_err(io_error).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        match format {
+            Format::WebP => writer.write_all(&webp(image)).map_err(io_error)?,
+            #[cfg(feature = "avif")]
+            Format::Avif => image.write_with_encoder(image::codecs::avif::AvifEncoder::new_with_speed_quality(
+                &mut writer,
+                8,
+                AVIF_QUALITY,
+            ))?,
+            Format::Original if is_webp(source) => writer.write_all(&webp(image)).map_err(io_error)?,
+            Format::Original if image.color().has_alpha() || !is_jpeg(source) => {
+                image.write_with_encoder(PngEncoder::new(&mut writer))?
+            }
+            Format::Original => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY))?,
+        }
+        writer.flush().map_err(io_error)
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(err);
+    }
+    fs::rename(&partial, target).map_err(io_error)
+}
+
+fn is_jpeg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg"))
+}
+
+// Width and height.
+type Size = (u32, u32);
+
+// Pages ask for the size of their images on every view, so it is kept until
+// the file changes instead of reading it again.
+fn dimensions(source: &Path) -> Result<Size, ImageError> {
+    static DIMENSIONS: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, Size)>>> = OnceLock::new();
+    let modified = fs::metadata(source)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|err| ImageError::Io {
+            path: source.to_path_buf(),
+            source: err,
+        })?;
+    let cache = DIMENSIONS.get_or_init(Mutex::default);
+    if let Some(&(cached, size)) = cache.lock().unwrap().get(source) {
+        if cached == modified {
+            return Ok(size);
+        }
+    }
+    let size = image::image_dimensions(source)?;
+    cache.lock().unwrap().insert(source.to_path_buf(), (modified, size));
+    Ok(size)
+}
+
+// The file for one variant of an image, generated if it is not in the cache
+// or is older than the original.
+pub fn variant(post: &Post, path: &str, width: u32, format: Format) -> Result<PathBuf, ImageError> {
+    let not_found = || ImageError::NotFound(format!("{}/{}", post.meta.archivo, variant_path(width, path, format)));
+    let source = post.asset(path).filter(|source| is_raster(source)).ok_or_else(not_found)?;
+    let (original, _) = dimensions(&source)?;
+    if !widths(original).contains(&width) || !formats(&source).any(|f| f == format) {
+        return Err(not_found());
+    }
+
+    let target = Path::new(IMAGE_CACHE_DIR)
+        .join(&post.meta.archivo)
+        .join(variant_path(width, path, format));
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    if let (Some(cached), Some(changed)) = (modified(&target), modified(&source)) {
+        if cached >= changed {
+            return Ok(target);
+        }
+    }
+
+    let mut image = ImageReader::open(&source)
+        .map_err(|err| ImageError::Io {
+            path: source.clone(),
+            source: err,
+        })?
+        .with_guessed_format()
+        .map_err(|err| ImageError::Io {
+            path: source.clone(),
+            source: err,
+        })?
+        .decode()?;
+    if width < original {
+        image = image.resize(width, u32::MAX, FilterType::Lanczos3);
+    }
+    encode(&image, &source, format, &target)?;
+    Ok(target)
+}
+
+// Every variant of every image of a post, as paths relative to `images_url`.
+pub fn variants(post: &Post) -> Vec<(String, u32, Format)> {
+    let mut variants = Vec::new();
+    for asset in post.assets() {
+        let Some(path) = asset.to_str().map(|path| path.replace('\\', "/")) else {
+            continue;
+        };
+        let source = post.dir.join(&asset);
+        if !is_raster(&source) {
+            continue;
+        }
+        let Ok((original, _)) = dimensions(&source) else {
+            continue;
+        };
+        for width in widths(original) {
+            for format in formats(&source) {
+                variants.push((path.clone(), width, format));
+            }
+        }
+    }
+    variants
+}
+
+// The smallest version of the cover of a post, for cards.
+pub fn thumbnail(post: &Post) -> Option<String> {
+    let archivo = &post.meta.archivo;
+    let path = post.meta.portada.as_deref()?.strip_prefix(&assets_url(archivo))?;
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let source = post.asset(&path).filter(|source| is_raster(source))?;
+    let (width, _) = dimensions(&source).ok()?;
+    Some(variant_url(archivo, &path, widths(width)[0], Format::Original))
+}
+
+pub fn variant_url(archivo: &str, path: &str, width: u32, format: Format) -> String {
+    let path = utf8_percent_encode(path, URL_PATH);
+    format!("{}{}", images_url(archivo), variant_path(width, &path.to_string(), format))
+}
+
+fn srcset(archivo: &str, path: &str, widths: &[u32], format: Format) -> String {
+    widths
+        .iter()
+        .map(|&width| format!("{} {width}w", variant_url(archivo, path, width, format)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Turns every `<img>` pointing to an image next to the post into a
+// `<picture>` with its variants. Its size is set so the page doesn't jump
+// around while images load.
+pub fn responsive_images(html: &str, post: &Post) -> String {
+    let archivo = &post.meta.archivo;
+    let assets = assets_url(archivo);
+
+    let rewritten = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("img[src]", |img| {
+                let src = img.get_attribute("src").unwrap_or_default();
+                let Some(path) = src.strip_prefix(&assets) else {
+                    return Ok(());
+                };
+                let path = percent_decode_str(&path.replace("&amp;", "&")).decode_utf8_lossy().into_owned();
+                let Some(source) = post.asset(&path).filter(|source| is_raster(source)) else {
+                    return Ok(());
+                };
+                let Ok((width, height)) = dimensions(&source) else {
+                    return Ok(());
+                };
+
+                let widths = widths(width);
+                let mut sources = String::new();
+                for format in formats(&source).filter(|&format| format != Format::Original) {
+                    sources.push_str(&format!(
+                        "<source type=\"{}\" srcset=\"{}\" sizes=\"{SIZES}\">",
+                        format.mime(),
+                        srcset(archivo, &path, &widths, format)
+                    ));
+                }
+                img.set_attribute("srcset", &srcset(archivo, &path, &widths, Format::Original))?;
+                img.set_attribute("sizes", SIZES)?;
+                img.set_attribute("width", &width.to_string())?;
+                img.set_attribute("height", &height.to_string())?;
+                // The cover says it is needed right away.
+                if !img.has_attribute("loading") {
+                    img.set_attribute("loading", "lazy")?;
+                }
+                img.set_attribute("decoding", "async")?;
+                img.before(&format!("<picture>{sources}"), ContentType::Html);
+                img.after("</picture>", ContentType::Html);
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    );
+
+    match rewritten {
+        Ok(html) => html,
+        Err(err) => {
+            leptos::logging::warn!("could not rewrite the images of {archivo}: {err}");
+            html.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("blog-images-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let value = (x * 7919 + y * 104729) % 251;
+            Rgb([value as u8, (value * 3 % 256) as u8, (x * y % 256) as u8])
+        }))
+    }
+
+    fn post(dir: &Path) -> Post {
+        Post {
+            meta: serde_yaml::from_str("titulo: T\nfecha: 01/01/2024\narchivo: hola.md\n").unwrap(),
+            source: String::new(),
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn widths_stop_at_the_original() {
+        assert_eq!(widths(1000), [480, 960, 1000]);
+        assert_eq!(widths(300), [300]);
+    }
+
+    #[test]
+    fn variants_of_webp_images_are_not_webp_again() {
+        assert!(formats(Path::new("foto.png")).any(|format| format == Format::WebP));
+        assert!(!formats(Path::new("foto.WEBP")).any(|format| format == Format::WebP));
+        assert_eq!(variant_path(480, "foto.png", Format::WebP), "480/foto.png.webp");
+        assert_eq!(variant_path(480, "foto.png", Format::Original), "480/foto.png");
+    }
+
+    #[test]
+    fn parses_the_format_out_of_variant_paths() {
+        let dir = temp_dir("parse");
+        fs::write(dir.join("foto.png"), "").unwrap();
+        let post = post(&dir);
+        let parsed = (
+            parse_variant(&post, "foto.png.webp"),
+            parse_variant(&post, "foto.png"),
+            parse_variant(&post, "otra.png.webp"),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(parsed.0, Some(("foto.png", Format::WebP)));
+        assert_eq!(parsed.1, Some(("foto.png", Format::Original)));
+        assert_eq!(parsed.2, None);
+    }
+
+    #[test]
+    fn partial_files_are_never_shared() {
+        let target = Path::new("/tmp/foto.png");
+        let (a, b) = (partial_path(target), partial_path(target));
+        assert_ne!(a, b);
+        assert_eq!(a.parent(), target.parent());
+    }
+
+    #[test]
+    fn webp_variants_are_lossy() {
+        let dir = temp_dir("webp");
+        let image = noise(128, 128);
+        let target = dir.join("foto.png.webp");
+        encode(&image, Path::new("foto.png"), Format::WebP, &target).unwrap();
+        let mut lossless = Vec::new();
+        image
+            .write_with_encoder(image::codecs::webp::WebPEncoder::new_lossless(&mut lossless))
+            .unwrap();
+        let written = fs::read(&target).unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(image::load_from_memory(&written).unwrap().width(), 128);
+        assert!(written.len() < lossless.len());
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn dimensions_are_read_again_when_the_image_changes() {
+        let dir = temp_dir("dimensions");
+        let path = dir.join("foto.png");
+        noise(20, 10).save(&path).unwrap();
+        let before = dimensions(&path).unwrap();
+        noise(30, 10).save(&path).unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        let after = dimensions(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before, (20, 10));
+        assert_eq!(after, (30, 10));
+    }
+
+    #[test]
+    fn images_of_the_post_become_pictures() {
+        let dir = temp_dir("responsive");
+        noise(600, 300).save(dir.join("foto.png")).unwrap();
+        let html = responsive_images(
+            "<p><img src=\"/post/hola.md/assets/foto.png\" alt=\"\"><img src=\"https://example.com/a.png\"></p>",
+            &post(&dir),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(html.contains("<picture><source type=\"image/webp\" srcset=\"/post/hola.md/images/480/foto.png.webp 480w, /post/hola.md/images/600/foto.png.webp 600w\""));
+        assert!(html.contains("width=\"600\" height=\"300\""));
+        assert!(html.contains("</picture>"));
+        assert!(html.contains("<img src=\"https://example.com/a.png\">"));
+    }
+
+    #[test]
+    fn only_images_without_a_loading_attribute_are_lazy() {
+        let dir = temp_dir("lazy");
+        noise(20, 20).save(dir.join("foto.png")).unwrap();
+        let html = responsive_images(
+            "<img src=\"/post/hola.md/assets/foto.png\" loading=\"eager\"><img src=\"/post/hola.md/assets/foto.png\">",
+            &post(&dir),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(html.matches("loading=\"eager\"").count(), 1);
+        assert_eq!(html.matches("loading=\"lazy\"").count(), 1);
+    }
+
+    #[test]
+    fn thumbnails_are_the_smallest_variant_of_the_cover() {
+        let dir = temp_dir("thumbnail");
+        noise(1000, 10).save(dir.join("mi foto.png")).unwrap();
+        let mut post = post(&dir);
+        let without_cover = thumbnail(&post);
+        post.meta.portada = Some("/post/hola.md/assets/mi%20foto.png".to_string());
+        let with_cover = thumbnail(&post);
+        post.meta.portada = Some("https://example.com/foto.png".to_string());
+        let external = thumbnail(&post);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(without_cover, None);
+        assert_eq!(with_cover.as_deref(), Some("/post/hola.md/images/480/mi%20foto.png"));
+        assert_eq!(external, None);
+    }
+}
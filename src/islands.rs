@@ -0,0 +1,162 @@
+use crate::directives::{escape_html, Directive};
+use leptos::*;
+use std::collections::BTreeMap;
+
+// Interactive components that can be placed inside a post with
+//
+//     ::island{name=Counter start=3}
+//
+// The server only writes a placeholder with the props, `mount_islands`
+// mounts the real component on the client once the post is in the DOM.
+
+pub type IslandProps = BTreeMap<String, String>;
+
+pub struct Island {
+    pub name: &'static str,
+    pub view: fn(&IslandProps) -> View,
+    // Plain HTML shown until the island is mounted.
+    pub fallback: fn(&IslandProps) -> String,
+}
+
+pub const ISLANDS: &[Island] = &[Island {
+    name: "Counter",
+    view: counter,
+    fallback: counter_fallback,
+}];
+
+pub fn find_island(name: &str) -> Option<&'static Island> {
+    ISLANDS.iter().find(|island| island.name == name)
+}
+
+pub fn island_directive(directive: &Directive, _content: &str) -> String {
+    let Some(island) = directive.attr("name").and_then(find_island) else {
+        return String::new();
+    };
+    let props: IslandProps = directive
+        .attrs
+        .iter()
+        .filter(|(key, _)| *key != "name")
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let json = serde_json::to_string(&props).unwrap_or_default();
+
+    format!(
+        "<div class=\"island\" data-island=\"{}\" data-props=\"{}\">{}</div>\n",
+        island.name,
+        escape_html(&json),
+        (island.fallback)(&props)
+    )
+}
+
+#[cfg(feature = "hydrate")]
+pub fn mount_islands() {
+    use leptos::wasm_bindgen::JsCast;
+
+    let Ok(placeholders) = document().query_selector_all("[data-island]:not([data-island-mounted])")
+    else {
+        return;
+    };
+
+    for i in 0..placeholders.length() {
+        let Some(element) = placeholders
+            .item(i)
+            .and_then(|node| node.dyn_into::<web_sys::HtmlElement>().ok())
+        else {
+            continue;
+        };
+        let Some(island) = element
+            .get_attribute("data-island")
+            .and_then(|name| find_island(&name))
+        else {
+            continue;
+        };
+        let props: IslandProps = element
+            .get_attribute("data-props")
+            .and_then(|props| serde_json::from_str(&props).ok())
+            .unwrap_or_default();
+
+        let _ = element.set_attribute("data-island-mounted", "");
+        element.set_inner_html("");
+        let view = island.view;
+        mount_to(element, move || view(&props));
+    }
+}
+
+fn counter_start(props: &IslandProps) -> i32 {
+    props
+        .get("start")
+        .and_then(|start| start.parse().ok())
+        .unwrap_or_default()
+}
+
+fn counter(props: &IslandProps) -> View {
+    let start = counter_start(props);
+    view! { <Counter start/> }.into_view()
+}
+
+fn counter_fallback(props: &IslandProps) -> String {
+    format!(
+        "<div class=\"inline-flex items-center gap-3 mb-4\"><span class=\"font-mono text-lg\">{}</span></div>",
+        counter_start(props)
+    )
+}
+
+#[component]
+pub fn Counter(#[prop(default = 0)] start: i32) -> impl IntoView {
+    let (count, set_count) = create_signal(start);
+
+    view! {
+        <div class="inline-flex items-center gap-3 mb-4">
+            <button
+                class="bg-gray-800 text-white rounded px-3 py-1"
+                on:click=move |_| set_count.update(|count| *count -= 1)
+            >
+                "-"
+            </button>
+            <span class="font-mono text-lg">{count}</span>
+            <button
+                class="bg-gray-800 text-white rounded px-3 py-1"
+                on:click=move |_| set_count.update(|count| *count += 1)
+            >
+                "+"
+            </button>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::directives::{parse_marker, Marker};
+
+    fn directive(line: &str) -> Directive {
+        match parse_marker(line) {
+            Some(Marker::Leaf(directive)) => directive,
+            marker => panic!("not a leaf directive: {marker:?}"),
+        }
+    }
+
+    #[test]
+    fn writes_a_placeholder_with_the_props() {
+        let html = island_directive(&directive(r#"::island{name=Counter start=3 nota="a \"b\""}"#), "");
+        assert!(html.starts_with(r#"<div class="island" data-island="Counter" data-props=""#));
+        assert!(html.contains("&quot;start&quot;:&quot;3&quot;"));
+        assert!(!html.contains("&quot;name&quot;"));
+        // The fallback shows the starting count until the island is mounted.
+        assert!(html.contains(r#"<span class="font-mono text-lg">3</span>"#));
+    }
+
+    #[test]
+    fn unknown_islands_render_nothing() {
+        assert_eq!(island_directive(&directive("::island{name=Desconocida}"), ""), "");
+        assert_eq!(island_directive(&directive("::island"), ""), "");
+    }
+
+    #[test]
+    fn counter_start_defaults_to_zero() {
+        let props = |start: &str| IslandProps::from([("start".to_string(), start.to_string())]);
+        assert_eq!(counter_start(&props("-2")), -2);
+        assert_eq!(counter_start(&props("muchos")), 0);
+        assert_eq!(counter_start(&IslandProps::new()), 0);
+    }
+}
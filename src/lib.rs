@@ -1,7 +1,47 @@
 pub mod app;
+pub mod assets;
+#[cfg(feature = "ssr")]
+pub mod check;
+#[cfg(feature = "ssr")]
+pub mod cli;
+#[cfg(feature = "ssr")]
+pub mod compress;
+#[cfg(feature = "ssr")]
+pub mod conditional;
+#[cfg(feature = "ssr")]
+pub mod content;
+pub mod directives;
 pub mod error_template;
 #[cfg(feature = "ssr")]
+pub mod export;
+#[cfg(feature = "ssr")]
+pub mod health;
+#[cfg(feature = "ssr")]
+pub mod images;
+pub mod islands;
+#[cfg(feature = "ssr")]
+pub mod link_cache;
+#[cfg(feature = "ssr")]
+pub mod metrics;
+#[cfg(feature = "ssr")]
+pub mod og;
+#[cfg(feature = "ssr")]
+pub mod render;
+#[cfg(feature = "ssr")]
+pub mod sanitize;
+#[cfg(feature = "ssr")]
+pub mod page_cache;
+#[cfg(feature = "ssr")]
+pub mod scaffold;
+#[cfg(feature = "ssr")]
+pub mod security;
+pub mod search;
+#[cfg(feature = "ssr")]
 pub mod fileserv;
+#[cfg(feature = "ssr")]
+pub mod server;
+#[cfg(feature = "ssr")]
+pub mod tls;
 
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
@@ -9,4 +49,5 @@ pub fn hydrate() {
     use crate::app::*;
     console_error_panic_hook::set_once();
     leptos::mount_to_body(App);
+    crate::islands::mount_islands();
 }
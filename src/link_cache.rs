@@ -0,0 +1,198 @@
+use crate::check::{ExternalLink, Problem};
+use leptos::logging;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+pub const LINK_CACHE_PATH: &str = ".link-cache.json";
+
+// Results older than this are checked again, unless running offline.
+const MAX_AGE_SECS: i64 = 7 * 24 * 60 * 60;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedLink {
+    // Unix time of the request.
+    checked: i64,
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+impl CachedLink {
+    fn is_ok(&self) -> bool {
+        self.status.is_some_and(|status| (200..400).contains(&status))
+    }
+
+    fn describe(&self) -> String {
+        match (&self.error, self.status) {
+            (Some(error), _) => error.clone(),
+            (None, Some(status)) => format!("status {status}"),
+            (None, None) => "no response".to_string(),
+        }
+    }
+}
+
+// Results of checking external URLs, kept in a JSON file so CI can run
+// `blog check --external --offline` against the results of a previous run.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinkCache {
+    links: BTreeMap<String, CachedLink>,
+}
+
+impl LinkCache {
+    // A missing or unreadable cache is the same as an empty one.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> CachedLink {
+    let checked = chrono::Utc::now().timestamp();
+    let mut response = client.head(url).send().await;
+    // Some servers don't implement HEAD.
+    if let Ok(head) = &response {
+        if matches!(head.status().as_u16(), 403 | 405 | 501) {
+            response = client.get(url).send().await;
+        }
+    }
+
+    match response {
+        Ok(response) => CachedLink {
+            checked,
+            status: Some(response.status().as_u16()),
+            error: None,
+        },
+        Err(err) => {
+            let mut error = err.to_string();
+            let mut source = std::error::Error::source(&err);
+            while let Some(cause) = source {
+                error = format!("{error}: {cause}");
+                source = cause.source();
+            }
+            CachedLink {
+                checked,
+                status: None,
+                error: Some(error),
+            }
+        }
+    }
+}
+
+// Checks every external link once, reusing recent results from the cache and,
+// for links that fail, from earlier in the same run. Offline, only cached
+// results are used and other links are skipped.
+pub async fn check_external(links: &[ExternalLink], cache: &mut LinkCache, offline: bool) -> Vec<Problem> {
+    let client = reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("blog-link-checker/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("the HTTP client has a valid configuration");
+    let now = chrono::Utc::now().timestamp();
+
+    let mut skipped = 0;
+    let mut problems = Vec::new();
+    let mut fetched: HashMap<&str, CachedLink> = HashMap::new();
+    for link in links {
+        let cached = cache.links.get(&link.url);
+        let result = match cached {
+            Some(cached) if offline || now - cached.checked < MAX_AGE_SECS => cached.clone(),
+            _ if offline => {
+                skipped += 1;
+                continue;
+            }
+            _ => match fetched.get(link.url.as_str()) {
+                Some(result) => result.clone(),
+                None => {
+                    let result = fetch(&client, &link.url).await;
+                    // Network errors are often temporary, only responses are
+                    // kept for later runs.
+                    if result.status.is_some() {
+                        cache.links.insert(link.url.clone(), result.clone());
+                    }
+                    fetched.insert(&link.url, result.clone());
+                    result
+                }
+            },
+        };
+
+        if !result.is_ok() {
+            problems.push(Problem {
+                path: link.path.clone(),
+                line: Some(link.line),
+                message: format!("broken external link {}: {}", link.url, result.describe()),
+            });
+        }
+    }
+
+    if skipped > 0 {
+        logging::warn!("{skipped} external links are not in the cache and were not checked");
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn link(url: &str, line: usize) -> ExternalLink {
+        ExternalLink {
+            path: PathBuf::from("post.md"),
+            line,
+            url: url.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_links_are_fetched_once_per_run() {
+        // Answers every connection with something that isn't HTTP.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, b"hola\r\n\r\n").await;
+            }
+        });
+
+        let mut cache = LinkCache::default();
+        let problems = check_external(&[link(&url, 1), link(&url, 7)], &mut cache, false).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[1].line, Some(7));
+        // Not kept for the next run.
+        assert!(cache.links.is_empty());
+    }
+
+    #[tokio::test]
+    async fn offline_uses_only_the_cache() {
+        let mut cache = LinkCache::default();
+        let old = chrono::Utc::now().timestamp() - 2 * MAX_AGE_SECS;
+        cache.links.insert(
+            "https://roto.example/".to_string(),
+            CachedLink {
+                checked: old,
+                status: Some(404),
+                error: None,
+            },
+        );
+        let links = [link("https://roto.example/", 1), link("https://nuevo.example/", 2)];
+        let problems = check_external(&links, &mut cache, true).await;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].message, "broken external link https://roto.example/: status 404");
+    }
+}
@@ -1,11 +1,35 @@
 #[cfg(feature = "ssr")]
 #[tokio::main]
-async fn main() {
-    use axum::Router;
+async fn main() -> std::process::ExitCode {
+    use blog::check::{check, index_link_problems, PUBLIC_DIR};
+    use blog::cli::{Cli, Command};
+    use blog::compress::precompress;
+    use blog::content::{configure, content_index, ContentConfig};
+    use blog::export::{export, route_paths};
+    use blog::health::{check_health, health_routes};
+    use blog::link_cache::{check_external, LinkCache};
+    use blog::metrics::{metrics_routes, track_metrics, METRICS_PATH};
+    use blog::page_cache::{PageCache, PURGE_PATH};
+    use blog::scaffold::new_post;
+    use blog::security::{self, security_headers, SecurityConfig};
+    use blog::server::router;
+    use blog::tls::{public_authority, redirect_to_https, reload_on_change, rustls_config, TlsConfig};
+    use axum::middleware;
+    use clap::Parser;
     use leptos::*;
-    use leptos_axum::{generate_route_list, LeptosRoutes};
-    use blog::app::*;
-    use blog::fileserv::file_and_error_handler;
+    use std::path::{Path, PathBuf};
+    use std::process::ExitCode;
+    use tower_http::compression::CompressionLayer;
+
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve(Box::default()));
+
+    let drafts = matches!(&command, Command::Serve(args) if args.drafts);
+    configure(ContentConfig {
+        dir: cli.content_dir.clone(),
+        drafts,
+        site_url: cli.site_url.clone(),
+    });
 
     // Setting get_configuration(None) means we'll be using cargo-leptos's env values
     // For deployment these variables are:
@@ -13,21 +37,154 @@ async fn main() {
     // Alternately a file can be specified such as Some("Cargo.toml")
     // The file would need to be included with the executable when moved to deployment
     let conf = get_configuration(None).await.unwrap();
-    let leptos_options = conf.leptos_options;
-    let addr = leptos_options.site_addr;
-    let routes = generate_route_list(App);
-
-    // build our application with a route
-    let app = Router::new()
-        .leptos_routes(&leptos_options, routes, App)
-        .fallback(file_and_error_handler)
-        .with_state(leptos_options);
-
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    logging::log!("listening on http://{}", &addr);
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    let mut leptos_options = conf.leptos_options;
+
+    match command {
+        Command::Serve(args) => {
+            if let Some(addr) = args.addr {
+                leptos_options.site_addr = addr;
+            }
+            let addr = leptos_options.site_addr;
+            security::configure(SecurityConfig {
+                content_security_policy: args.security.csp.clone(),
+                csp_report_only: args.security.csp_report_only,
+                strict_transport_security: args.security.hsts.clone(),
+                referrer_policy: args.security.referrer_policy.clone(),
+                permissions_policy: args.security.permissions_policy.clone(),
+                live_reload: leptos_options.env == leptos_config::Env::DEV,
+            });
+
+            match content_index() {
+                Ok(index) => {
+                    let site_root = Path::new(&leptos_options.site_root);
+                    for problem in index_link_problems(&index, &route_paths(), site_root) {
+                        logging::warn!("{problem}");
+                    }
+                }
+                Err(err) => logging::warn!("{err}"),
+            }
+
+            let cache = args.page_cache.then(|| {
+                PageCache::new(
+                    args.page_cache_pages,
+                    args.page_cache_mb * 1024 * 1024,
+                    args.admin_token.clone(),
+                )
+            });
+            match &cache {
+                Some(cache) if cache.has_admin() => logging::log!("page cache on, purge it with POST {PURGE_PATH}"),
+                Some(_) => logging::log!("page cache on"),
+                None => {}
+            }
+            if args.admin_token.is_some() {
+                logging::log!("metrics at {METRICS_PATH}");
+            }
+
+            // build our application with a route. Files with a precompressed
+            // copy are sent as they are, everything else is compressed here.
+            let app = router(leptos_options, cache)
+                .merge(health_routes())
+                .merge(metrics_routes(args.admin_token.as_deref()))
+                .layer(middleware::from_fn(security_headers))
+                .layer(CompressionLayer::new())
+                .layer(middleware::from_fn(track_metrics));
+
+            let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) else {
+                let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+                logging::log!("listening on http://{}", &addr);
+                axum::serve(listener, app.into_make_service())
+                    .await
+                    .unwrap();
+                return ExitCode::SUCCESS;
+            };
+
+            let tls = TlsConfig { cert, key };
+            let config = match rustls_config(&tls).await {
+                Ok(config) => config,
+                Err(err) => {
+                    logging::error!("could not load the certificate from {}: {err}", tls.cert.display());
+                    return ExitCode::FAILURE;
+                }
+            };
+            reload_on_change(config.clone(), tls);
+            if let Some(redirect_addr) = args.redirect_addr {
+                let public = args
+                    .redirect_to
+                    .or_else(|| blog::content::config().site_url.as_deref().and_then(public_authority));
+                tokio::spawn(redirect_to_https(redirect_addr, public, addr.port()));
+            }
+            logging::log!("listening on https://{}", &addr);
+            axum_server::bind_rustls(addr, config)
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+            ExitCode::SUCCESS
+        }
+        Command::Export(args) => match export(&leptos_options, &args.out_dir).await {
+            Ok(pages) => {
+                logging::log!("exported {pages} pages to {}", args.out_dir.display());
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                logging::error!("export failed: {err}");
+                ExitCode::FAILURE
+            }
+        },
+        Command::Compress(args) => {
+            let dir = args.dir.unwrap_or_else(|| PathBuf::from(&leptos_options.site_root));
+            match precompress(&dir) {
+                Ok(files) => {
+                    logging::log!("compressed {files} files in {}", dir.display());
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    logging::error!("could not compress {}: {err}", dir.display());
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Check(args) => {
+            let report = check(&cli.content_dir, &route_paths(), Path::new(PUBLIC_DIR));
+            let mut problems = report.problems;
+            if args.external {
+                let mut cache = LinkCache::load(&args.link_cache);
+                problems.extend(check_external(&report.external, &mut cache, args.offline).await);
+                if let Err(err) = cache.save(&args.link_cache) {
+                    logging::warn!("could not write {}: {err}", args.link_cache.display());
+                }
+            }
+            for problem in &problems {
+                logging::error!("{problem}");
+            }
+            if problems.is_empty() {
+                logging::log!("no problems found in {}", cli.content_dir.display());
+                ExitCode::SUCCESS
+            } else {
+                logging::error!("{} problems found", problems.len());
+                ExitCode::FAILURE
+            }
+        }
+        Command::Health(args) => {
+            let addr = args.addr.unwrap_or(leptos_options.site_addr);
+            match check_health(addr, args.tls_cert.is_some()).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    logging::error!("{err}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::New(args) => match new_post(&cli.content_dir, &args.title) {
+            Ok(dir) => {
+                logging::log!("created {} as a draft, preview it with `blog serve --drafts`", dir.display());
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                logging::error!("could not create the post: {err}");
+                ExitCode::FAILURE
+            }
+        },
+    }
 }
 
 #[cfg(not(feature = "ssr"))]
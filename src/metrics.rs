@@ -0,0 +1,238 @@
+use crate::content::content_index;
+use crate::security::authorized;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+// Prometheus metrics at `/metrics`, for whoever has the admin token.
+// Requests are labelled with the route they matched, or `fallback` for
+// static files and pages that don't exist, so every path doesn't become its
+// own series, and with their method if it is a standard one. Latencies are
+// measured until the response starts, streamed pages finish later.
+
+pub const METRICS_PATH: &str = "/metrics";
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+    server_fns: IntCounterVec,
+    page_cache: IntCounterVec,
+    posts: IntGauge,
+    reloads: IntCounter,
+}
+
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| {
+        let requests = IntCounterVec::new(
+            Opts::new("blog_http_requests_total", "HTTP requests by route, method and status"),
+            &["route", "method", "status"],
+        )
+        .unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "blog_http_request_duration_seconds",
+                "Time until the response starts, by route and method",
+            ),
+            &["route", "method"],
+        )
+        .unwrap();
+        let server_fns = IntCounterVec::new(
+            Opts::new("blog_server_fn_calls_total", "Server function calls by name"),
+            &["name"],
+        )
+        .unwrap();
+        let page_cache = IntCounterVec::new(
+            Opts::new("blog_page_cache_requests_total", "Page cache lookups, hit or miss"),
+            &["result"],
+        )
+        .unwrap();
+        let posts = IntGauge::new("blog_content_posts", "Posts in the content index").unwrap();
+        let reloads = IntCounter::new("blog_content_reloads_total", "Times the content index was reloaded").unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(server_fns.clone())).unwrap();
+        registry.register(Box::new(page_cache.clone())).unwrap();
+        registry.register(Box::new(posts.clone())).unwrap();
+        registry.register(Box::new(reloads.clone())).unwrap();
+
+        // Known from the start, so they show up with 0 before the first call.
+        for name in crate::app::SERVER_FNS {
+            server_fns.with_label_values(&[name]);
+        }
+        for result in ["hit", "miss"] {
+            page_cache.with_label_values(&[result]);
+        }
+
+        Metrics {
+            registry,
+            requests,
+            latency,
+            server_fns,
+            page_cache,
+            posts,
+            reloads,
+        }
+    })
+}
+
+pub fn count_server_fn(name: &str) {
+    metrics().server_fns.with_label_values(&[name]).inc();
+}
+
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "other",
+    }
+}
+
+pub async fn track_metrics(request: Request, next: Next) -> Response {
+    let metrics = metrics();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("fallback", |path| path.as_str())
+        .to_string();
+    let method = method_label(request.method());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let status = response.status().as_u16().to_string();
+    metrics
+        .requests
+        .with_label_values(&[&route, method, &status])
+        .inc();
+    metrics
+        .latency
+        .with_label_values(&[&route, method])
+        .observe(elapsed);
+    if let Some(result) = response
+        .headers()
+        .get("x-page-cache")
+        .and_then(|value| value.to_str().ok())
+    {
+        metrics.page_cache.with_label_values(&[result]).inc();
+    }
+    response
+}
+
+async fn metrics_handler(State(token): State<Arc<str>>, headers: HeaderMap) -> Response {
+    if !authorized(&headers, &token) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let metrics = metrics();
+    // The index is reloaded on demand, so it is read when scraped.
+    if let Ok(index) = content_index() {
+        metrics.posts.set(index.posts.len() as i64);
+        let reloads = index.version.saturating_sub(1);
+        metrics
+            .reloads
+            .inc_by(reloads.saturating_sub(metrics.reloads.get()));
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    if let Err(err) = encoder.encode(&metrics.registry.gather(), &mut body) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+    }
+    ([(header::CONTENT_TYPE, encoder.format_type().to_string())], body).into_response()
+}
+
+// Scraped with `Authorization: Bearer <token>`, and not served at all
+// without a token.
+pub fn metrics_routes(admin_token: Option<&str>) -> Router {
+    let Some(token) = admin_token else {
+        return Router::new();
+    };
+    Router::new().route(METRICS_PATH, get(metrics_handler).with_state(Arc::<str>::from(token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::middleware;
+    use tower::ServiceExt;
+
+    async fn scrape(app: Router, token: Option<&str>) -> (StatusCode, String) {
+        let mut request = Request::get(METRICS_PATH);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let response = app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn metrics_need_the_admin_token() {
+        let (without_token, _) = scrape(metrics_routes(None), None).await;
+        let (anonymous, _) = scrape(metrics_routes(Some("secreto")), None).await;
+        let (wrong, _) = scrape(metrics_routes(Some("secreto")), Some("otro")).await;
+        let (admin, body) = scrape(metrics_routes(Some("secreto")), Some("secreto")).await;
+
+        assert_eq!(without_token, StatusCode::NOT_FOUND);
+        assert_eq!(anonymous, StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong, StatusCode::UNAUTHORIZED);
+        assert_eq!(admin, StatusCode::OK);
+        assert!(body.contains("blog_server_fn_calls_total{name=\"GetPost\"} 0"));
+    }
+
+    #[tokio::test]
+    async fn requests_are_counted_by_the_route_they_matched() {
+        let app = Router::new()
+            .route("/contado/:id", get(|| async { "hola" }))
+            .layer(middleware::from_fn(track_metrics));
+        for id in ["1", "2"] {
+            let request = Request::get(format!("/contado/{id}")).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let count = metrics()
+            .requests
+            .with_label_values(&["/contado/:id", "GET", "200"])
+            .get();
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn unknown_methods_share_a_label() {
+        let app = Router::new()
+            .route("/metodo", get(|| async { "hola" }))
+            .layer(middleware::from_fn(track_metrics));
+        for method in ["BREW", "PROPFIND"] {
+            let request = Request::builder().method(method).uri("/metodo").body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let count = metrics()
+            .requests
+            .with_label_values(&["/metodo", "other", "405"])
+            .get();
+        assert_eq!(count, 2);
+        assert_eq!(method_label(&Method::PATCH), "PATCH");
+    }
+}
@@ -0,0 +1,167 @@
+use crate::app::SITE_NAME;
+use crate::content::Post;
+use crate::directives::escape_html;
+use crate::images::partial_path;
+use resvg::{tiny_skia, usvg};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use thiserror::Error;
+
+// Social card for posts without a cover: the SVG template is filled with the
+// title, date and site name and rasterised to a PNG.
+
+pub const OG_CACHE_DIR: &str = ".cache/og";
+
+const TEMPLATE: &str = include_str!("og.svg");
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 630;
+// Characters per line and lines of the title at 56px on 1040px.
+const LINE_LENGTH: usize = 26;
+const MAX_LINES: usize = 3;
+const LINE_HEIGHT: usize = 72;
+
+#[derive(Debug, Error)]
+pub enum OgError {
+    #[error("{path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid card template: {0}")]
+    Svg(#[from] usvg::Error),
+    #[error("could not rasterise the card")]
+    Render,
+}
+
+pub fn og_url(archivo: &str) -> String {
+    format!("/og/{archivo}.png")
+}
+
+fn fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut fonts = usvg::fontdb::Database::new();
+            fonts.load_system_fonts();
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+// SVG text doesn't wrap, so the title is split in lines here, the last one
+// ending with "…" when it doesn't fit.
+fn title_lines(title: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in title.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= LINE_LENGTH => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
+
+fn svg(post: &Post) -> String {
+    let lines = title_lines(&post.meta.titulo);
+    let tspans: String = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let dy = if i == 0 { 0 } else { LINE_HEIGHT };
+            format!("<tspan x=\"80\" dy=\"{dy}\">{}</tspan>", escape_html(line))
+        })
+        .collect();
+    // Centred between the site name and the date.
+    let titulo_y = 340 - (lines.len().saturating_sub(1) * LINE_HEIGHT) / 2;
+
+    TEMPLATE
+        .replace("{sitio}", &escape_html(SITE_NAME))
+        .replace("{fecha}", &escape_html(&post.meta.fecha))
+        .replace("{titulo_y}", &titulo_y.to_string())
+        .replace("{titulo}", &tspans)
+}
+
+fn render(svg: &str) -> Result<Vec<u8>, OgError> {
+    let options = usvg::Options {
+        fontdb: fonts(),
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options)?;
+    let mut pixmap = tiny_skia::Pixmap::new(WIDTH, HEIGHT).ok_or(OgError::Render)?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|_| OgError::Render)
+}
+
+// The card of a post, rendered again only when the template or the
+// metadata on it change.
+pub fn og_image(post: &Post) -> Result<PathBuf, OgError> {
+    let svg = svg(post);
+    let hash = Sha256::digest(svg.as_bytes());
+    let hash: String = hash[..8].iter().map(|byte| format!("{byte:02x}")).collect();
+    let path = Path::new(OG_CACHE_DIR).join(format!("{}-{hash}.png", post.meta.archivo));
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    let io_error = |source| OgError::Io {
+        path: path.clone(),
+        source,
+    };
+    let png = render(&svg)?;
+    fs::create_dir_all(OG_CACHE_DIR).map_err(io_error)?;
+    let partial = partial_path(&path);
+    if let Err(err) = fs::write(&partial, png) {
+        let _ = fs::remove_file(&partial);
+        return Err(io_error(err));
+    }
+    fs::rename(&partial, &path).map_err(io_error)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(titulo: &str) -> Post {
+        Post {
+            meta: serde_yaml::from_str(&format!("titulo: \"{titulo}\"\nfecha: 01/01/2024\narchivo: hola.md\n")).unwrap(),
+            source: String::new(),
+            dir: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn long_titles_are_wrapped_and_cut() {
+        assert_eq!(title_lines("Hola mundo"), ["Hola mundo"]);
+        assert_eq!(
+            title_lines("Una palabra detrás de otra hasta que el título no entra en una sola línea de la tarjeta"),
+            ["Una palabra detrás de otra", "hasta que el título no", "entra en una sola línea de…"]
+        );
+    }
+
+    #[test]
+    fn titles_are_escaped_in_the_template() {
+        let svg = svg(&post("<b> & co"));
+        assert!(svg.contains("<tspan x=\"80\" dy=\"0\">&lt;b&gt; &amp; co</tspan>"));
+        assert!(svg.contains("01/01/2024"));
+        assert!(!svg.contains("{titulo}"));
+    }
+
+    #[test]
+    fn cards_are_png_of_the_card_size() {
+        let png = render(&svg(&post("Hola"))).unwrap();
+        let card = image::load_from_memory(&png).unwrap();
+        assert_eq!((card.width(), card.height()), (WIDTH, HEIGHT));
+    }
+}
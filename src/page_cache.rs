@@ -0,0 +1,295 @@
+use crate::content::content_index;
+use crate::security::{authorized, content_security_policy, csp_header};
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use leptos::nonce::Nonce;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Rendered pages, kept until the posts change. Each response says whether
+// it came from here in `X-Page-Cache`.
+
+pub const PURGE_PATH: &str = "/admin/page-cache/purge";
+
+struct Page {
+    headers: HeaderMap,
+    body: Bytes,
+    // When it was last served, to drop the least recently used first.
+    used: u64,
+}
+
+#[derive(Default)]
+struct Pages {
+    pages: HashMap<String, Page>,
+    // Version of the content index the pages were rendered with.
+    version: u64,
+    bytes: usize,
+    clock: u64,
+}
+
+impl Pages {
+    fn clear(&mut self) -> usize {
+        let purged = self.pages.len();
+        self.pages.clear();
+        self.bytes = 0;
+        purged
+    }
+
+    fn get(&mut self, path: &str) -> Option<(HeaderMap, Bytes)> {
+        self.clock += 1;
+        let page = self.pages.get_mut(path)?;
+        page.used = self.clock;
+        Some((page.headers.clone(), page.body.clone()))
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .pages
+            .iter()
+            .min_by_key(|(_, page)| page.used)
+            .map(|(path, _)| path.clone());
+        if let Some(page) = oldest.and_then(|path| self.pages.remove(&path)) {
+            self.bytes -= page.body.len();
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PageCache {
+    pages: Arc<Mutex<Pages>>,
+    max_pages: usize,
+    max_bytes: usize,
+    admin_token: Option<String>,
+}
+
+impl PageCache {
+    pub fn new(max_pages: usize, max_bytes: usize, admin_token: Option<String>) -> Self {
+        PageCache {
+            pages: Arc::default(),
+            max_pages,
+            max_bytes,
+            admin_token,
+        }
+    }
+
+    pub fn has_admin(&self) -> bool {
+        self.admin_token.is_some()
+    }
+
+    fn insert(&self, path: String, headers: HeaderMap, body: Bytes, version: u64) {
+        if body.len() > self.max_bytes || self.max_pages == 0 {
+            return;
+        }
+        let mut pages = self.pages.lock().unwrap();
+        // Rendered with an older index while the posts changed.
+        if pages.version != version {
+            return;
+        }
+        if let Some(old) = pages.pages.remove(&path) {
+            pages.bytes -= old.body.len();
+        }
+        while pages.pages.len() >= self.max_pages || pages.bytes + body.len() > self.max_bytes {
+            pages.evict();
+        }
+        pages.clock += 1;
+        pages.bytes += body.len();
+        let used = pages.clock;
+        pages.pages.insert(path, Page { headers, body, used });
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        self.admin_token
+            .as_deref()
+            .is_some_and(|token| authorized(headers, token))
+    }
+}
+
+fn cache_header(response: &mut Response, status: &'static str) {
+    response
+        .headers_mut()
+        .insert("x-page-cache", HeaderValue::from_static(status));
+}
+
+fn is_page(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|kind| kind.to_str().ok())
+        .is_some_and(|kind| kind.starts_with("text/html"))
+}
+
+// The nonce of the inline scripts of a page, from its policy.
+fn page_nonce(headers: &HeaderMap) -> Option<String> {
+    let policy = headers.get(csp_header())?.to_str().ok()?;
+    let start = policy.find("'nonce-")? + "'nonce-".len();
+    let end = start + policy[start..].find('\'')?;
+    Some(policy[start..end].to_string())
+}
+
+// Nonces must not be used twice, so every copy of a page gets a new one.
+fn renew_nonce(mut headers: HeaderMap, body: Bytes) -> (HeaderMap, Bytes) {
+    let Some(old) = page_nonce(&headers) else {
+        return (headers, body);
+    };
+    let new = Nonce::new();
+    if let Some(policy) = content_security_policy(Some(&new)) {
+        headers.insert(csp_header(), policy);
+    }
+    let body = String::from_utf8_lossy(&body)
+        .replace(&format!("nonce=\"{old}\""), &format!("nonce=\"{new}\""));
+    (headers, Bytes::from(body))
+}
+
+pub async fn page_cache(State(cache): State<PageCache>, request: Request, next: Next) -> Response {
+    // Pages with a query string are rare here, and not worth keeping.
+    if !matches!(*request.method(), Method::GET | Method::HEAD) || request.uri().query().is_some() {
+        return next.run(request).await;
+    }
+    let Ok(index) = content_index() else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path().to_string();
+    let is_get = request.method() == Method::GET;
+
+    let cached = {
+        let mut pages = cache.pages.lock().unwrap();
+        if pages.version != index.version {
+            pages.clear();
+            pages.version = index.version;
+        }
+        pages.get(&path)
+    };
+    if let Some((headers, body)) = cached {
+        let (headers, body) = renew_nonce(headers, body);
+        let mut response = Response::new(Body::from(body));
+        *response.headers_mut() = headers;
+        cache_header(&mut response, "hit");
+        return response;
+    }
+
+    let response = next.run(request).await;
+    if !is_get || response.status() != StatusCode::OK || !is_page(response.headers()) {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+        }
+    };
+    cache.insert(path, parts.headers.clone(), body.clone(), index.version);
+    let mut response = Response::from_parts(parts, Body::from(body));
+    cache_header(&mut response, "miss");
+    response
+}
+
+// `POST /admin/page-cache/purge` with `Authorization: Bearer <token>`.
+pub async fn purge_handler(State(cache): State<PageCache>, headers: HeaderMap) -> Response {
+    if !cache.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let purged = cache.pages.lock().unwrap().clear();
+    leptos::logging::log!("purged {purged} pages from the page cache");
+    axum::Json(serde_json::json!({ "purged": purged })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(cache: &PageCache, path: &str) -> Option<Bytes> {
+        cache.pages.lock().unwrap().get(path).map(|(_, body)| body)
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {token}")).unwrap());
+        headers
+    }
+
+    #[test]
+    fn cached_pages_get_a_new_nonce() {
+        let mut headers = HeaderMap::new();
+        headers.insert(csp_header(), content_security_policy(Some("viejo")).unwrap());
+        let body = Bytes::from(r#"<script nonce="viejo">a()</script><p>nonce="viejo"</p>"#);
+
+        let (headers, body) = renew_nonce(headers, body);
+        let new = page_nonce(&headers).unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert_ne!(new, "viejo");
+        assert_eq!(body.matches(&format!("nonce=\"{new}\"")).count(), 2);
+        assert!(!body.contains("viejo"));
+    }
+
+    #[test]
+    fn pages_without_a_nonce_are_left_alone() {
+        let body = Bytes::from("<p>hola</p>");
+        let (headers, renewed) = renew_nonce(HeaderMap::new(), body.clone());
+        assert!(headers.is_empty());
+        assert_eq!(renewed, body);
+    }
+
+    #[test]
+    fn the_least_recently_used_page_goes_first() {
+        let cache = PageCache::new(2, 1000, None);
+        cache.insert("/a".into(), HeaderMap::new(), Bytes::from("a"), 0);
+        cache.insert("/b".into(), HeaderMap::new(), Bytes::from("b"), 0);
+        page(&cache, "/a");
+        cache.insert("/c".into(), HeaderMap::new(), Bytes::from("c"), 0);
+
+        assert!(page(&cache, "/a").is_some());
+        assert!(page(&cache, "/b").is_none());
+        assert!(page(&cache, "/c").is_some());
+    }
+
+    #[test]
+    fn pages_are_evicted_to_stay_under_the_size() {
+        let cache = PageCache::new(10, 10, None);
+        cache.insert("/a".into(), HeaderMap::new(), Bytes::from("aaaaaa"), 0);
+        cache.insert("/b".into(), HeaderMap::new(), Bytes::from("bbbbbb"), 0);
+        cache.insert("/big".into(), HeaderMap::new(), Bytes::from("x".repeat(11)), 0);
+
+        assert!(page(&cache, "/a").is_none());
+        assert!(page(&cache, "/b").is_some());
+        assert!(page(&cache, "/big").is_none());
+        assert_eq!(cache.pages.lock().unwrap().bytes, 6);
+    }
+
+    #[test]
+    fn pages_of_an_older_index_are_not_kept() {
+        let cache = PageCache::new(10, 1000, None);
+        cache.pages.lock().unwrap().version = 2;
+        cache.insert("/a".into(), HeaderMap::new(), Bytes::from("a"), 1);
+        assert!(page(&cache, "/a").is_none());
+    }
+
+    #[test]
+    fn purging_needs_the_admin_token() {
+        let cache = PageCache::new(10, 1000, Some("secreto".to_string()));
+        assert!(cache.authorized(&bearer("secreto")));
+        assert!(!cache.authorized(&bearer("secret")));
+        assert!(!cache.authorized(&bearer("secretos")));
+        assert!(!cache.authorized(&HeaderMap::new()));
+        assert!(!PageCache::new(10, 1000, None).authorized(&bearer("")));
+    }
+
+    #[tokio::test]
+    async fn purging_empties_the_cache() {
+        let cache = PageCache::new(10, 1000, Some("secreto".to_string()));
+        cache.insert("/a".into(), HeaderMap::new(), Bytes::from("a"), 0);
+
+        let refused = purge_handler(State(cache.clone()), bearer("otro")).await;
+        let kept = page(&cache, "/a").is_some();
+        let purged = purge_handler(State(cache.clone()), bearer("secreto")).await;
+        let body = to_bytes(purged.into_body(), usize::MAX).await.unwrap();
+
+        assert_eq!(refused.status(), StatusCode::UNAUTHORIZED);
+        assert!(kept);
+        assert_eq!(&body[..], br#"{"purged":1}"#);
+        assert!(page(&cache, "/a").is_none());
+    }
+}
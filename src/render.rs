@@ -0,0 +1,502 @@
+use crate::directives::{parse_marker, Directive, DirectiveHandler, Directives, Marker};
+use crate::sanitize::{sanitize, sanitize_with_assets, ContentSource};
+use markdown::mdast::Node;
+
+enum Segment {
+    Markdown(String),
+    Html(String),
+    Container(Directive, DirectiveHandler, Vec<Segment>),
+}
+
+enum Container {
+    Directive(Directive, DirectiveHandler),
+    // A container without a handler is left as text, opener and closer
+    // included, but still has to take its closing marker.
+    Unknown(String),
+}
+
+struct Frame {
+    container: Option<Container>,
+    segments: Vec<Segment>,
+}
+
+impl Frame {
+    fn new(container: Option<Container>) -> Self {
+        Frame {
+            container,
+            segments: Vec::new(),
+        }
+    }
+
+    fn push_markdown(&mut self, text: &str) {
+        match self.segments.last_mut() {
+            Some(Segment::Markdown(markdown)) => markdown.push_str(text),
+            _ => self.segments.push(Segment::Markdown(text.to_string())),
+        }
+    }
+}
+
+struct Renderer<'a> {
+    // Every line handled is a slice of it.
+    source: &'a str,
+    directives: &'a Directives,
+    stack: Vec<Frame>,
+    definitions: String,
+    // Directives without a handler and their line, shown as text.
+    unknown: Vec<(String, usize)>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(source: &'a str, directives: &'a Directives) -> Self {
+        let mut renderer = Renderer {
+            source,
+            directives,
+            stack: vec![Frame::new(None)],
+            definitions: String::new(),
+            unknown: Vec::new(),
+        };
+        renderer.process(source);
+
+        // Containers left open at the end of the file are closed implicitly.
+        while renderer.stack.len() > 1 {
+            renderer.close(None);
+        }
+        renderer
+    }
+}
+
+// Renders a post to HTML. Directives are found by walking the top-level
+// blocks of the mdast, so a `:::` inside a code fence is left untouched.
+// The result is sanitised according to where the Markdown came from.
+// `assets` is where relative links and images are served from, see
+// `sanitize_with_assets`.
+pub fn render_markdown(
+    source: &str,
+    directives: &Directives,
+    content_source: ContentSource,
+    assets: Option<&str>,
+) -> String {
+    let mut renderer = Renderer::new(source, directives);
+    let options = markdown::Options {
+        compile: markdown::CompileOptions {
+            allow_dangerous_html: content_source.allows_raw_html(),
+            ..markdown::CompileOptions::default()
+        },
+        ..markdown::Options::default()
+    };
+    let root = renderer.stack.pop().expect("the root frame is never popped");
+    let html = to_html(&root.segments, &renderer.definitions, &options);
+    match assets {
+        Some(assets) => sanitize_with_assets(&html, content_source, assets),
+        None => sanitize(&html, content_source),
+    }
+}
+
+// Directives in a post that have no handler, with their line, found the same
+// way `render_markdown` finds them.
+pub fn unknown_directives(source: &str, directives: &Directives) -> Vec<(String, usize)> {
+    Renderer::new(source, directives).unknown
+}
+
+impl Renderer<'_> {
+    fn current(&mut self) -> &mut Frame {
+        self.stack.last_mut().expect("the root frame is never popped")
+    }
+
+    fn process(&mut self, source: &str) {
+        let Ok(tree) = markdown::to_mdast(source, &markdown::ParseOptions::default()) else {
+            self.current().push_markdown(source);
+            return;
+        };
+        let Some(blocks) = tree.children() else {
+            return;
+        };
+
+        for block in blocks {
+            let Some(position) = block.position() else {
+                continue;
+            };
+            // Start at the beginning of the line to keep the block indentation.
+            let start = source[..position.start.offset]
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+            let text = &source[start..position.end.offset];
+
+            match block {
+                Node::Definition(_) => {
+                    self.definitions.push_str(text);
+                    self.definitions.push('\n');
+                }
+                Node::Paragraph(_) => {
+                    for line in text.lines() {
+                        self.line(line);
+                    }
+                    self.current().push_markdown("\n");
+                }
+                Node::Code(_) | Node::Html(_) | Node::Heading(_) | Node::ThematicBreak(_) => {
+                    self.block(text);
+                }
+                _ => match lazy_marker(text) {
+                    // An unindented marker right after a list or a quote is
+                    // swallowed as a lazy continuation line, split it off and
+                    // start over from there.
+                    Some(split) => {
+                        self.block(&text[..split]);
+                        self.process(&source[start + split..]);
+                        return;
+                    }
+                    None => self.block(text),
+                },
+            }
+        }
+    }
+
+    fn block(&mut self, text: &str) {
+        let frame = self.current();
+        frame.push_markdown(text.trim_end_matches('\n'));
+        frame.push_markdown("\n\n");
+    }
+
+    fn line_number(&self, line: &str) -> usize {
+        let offset = line.as_ptr() as usize - self.source.as_ptr() as usize;
+        self.source[..offset].matches('\n').count() + 1
+    }
+
+    fn line(&mut self, line: &str) {
+        match parse_marker(line) {
+            Some(Marker::Open(directive)) => {
+                let container = match self.directives.get(&directive.name) {
+                    Some(handler) => Container::Directive(directive, handler),
+                    None => {
+                        self.unknown.push((directive.name, self.line_number(line)));
+                        Container::Unknown(line.to_string())
+                    }
+                };
+                self.stack.push(Frame::new(Some(container)));
+            }
+            Some(Marker::Close) if self.stack.len() > 1 => self.close(Some(line)),
+            Some(Marker::Leaf(directive)) => match self.directives.get(&directive.name) {
+                Some(handler) => {
+                    let html = handler(&directive, "");
+                    self.current().segments.push(Segment::Html(html));
+                }
+                None => {
+                    self.unknown.push((directive.name, self.line_number(line)));
+                    self.text(line);
+                }
+            },
+            _ => self.text(line),
+        }
+    }
+
+    fn text(&mut self, line: &str) {
+        let frame = self.current();
+        frame.push_markdown(line);
+        frame.push_markdown("\n");
+    }
+
+    // `line` is the closing marker, or `None` at the end of the file.
+    fn close(&mut self, line: Option<&str>) {
+        let frame = self.stack.pop().expect("the root frame is never popped");
+        match frame.container {
+            Some(Container::Directive(directive, handler)) => {
+                self.current()
+                    .segments
+                    .push(Segment::Container(directive, handler, frame.segments));
+            }
+            Some(Container::Unknown(opener)) => {
+                self.text(&opener);
+                for segment in frame.segments {
+                    match segment {
+                        Segment::Markdown(text) => self.current().push_markdown(&text),
+                        segment => self.current().segments.push(segment),
+                    }
+                }
+                if let Some(line) = line {
+                    self.text(line);
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+fn lazy_marker(text: &str) -> Option<usize> {
+    let mut offset = 0;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        if i > 0 && line.starts_with("::") && parse_marker(line).is_some() {
+            return Some(offset);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn to_html(segments: &[Segment], definitions: &str, options: &markdown::Options) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            // Link reference definitions are repeated in every chunk so
+            // references keep working across directive boundaries.
+            Segment::Markdown(text) => {
+                markdown::to_html_with_options(&format!("{text}\n{definitions}"), options)
+                    .unwrap_or_default()
+            }
+            Segment::Html(html) => html.clone(),
+            Segment::Container(directive, handler, segments) => {
+                handler(directive, &to_html(segments, definitions, options))
+            }
+        })
+        .collect()
+}
+
+// Fenced code is only kept with `code`.
+fn collect_text(node: &Node, text: &mut String, code: bool) {
+    match node {
+        Node::Text(node) => text.push_str(&node.value),
+        Node::InlineCode(node) => text.push_str(&node.value),
+        Node::Code(node) => {
+            if code {
+                text.push_str(&node.value);
+                text.push('\n');
+            }
+        }
+        Node::Break(_) => text.push('\n'),
+        _ => {
+            if let Some(children) = node.children() {
+                for child in children {
+                    collect_text(child, text, code);
+                }
+            }
+            let inline = matches!(
+                node,
+                Node::Emphasis(_)
+                    | Node::Strong(_)
+                    | Node::Delete(_)
+                    | Node::Link(_)
+                    | Node::LinkReference(_)
+            );
+            if !inline {
+                text.push('\n');
+            }
+        }
+    }
+}
+
+fn without_markers(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().filter(|line| parse_marker(line).is_none())
+}
+
+fn text_content(source: &str, code: bool) -> String {
+    let mut text = String::new();
+    if let Ok(tree) = markdown::to_mdast(source, &markdown::ParseOptions::default()) {
+        collect_text(&tree, &mut text, code);
+    }
+    without_markers(&text).collect::<Vec<_>>().join("\n")
+}
+
+// Text content of a post without markup or directive lines, used for search.
+pub fn plain_text(source: &str) -> String {
+    text_content(source, true)
+}
+
+// Like `plain_text` without fenced code, which nobody reads word by word,
+// used for word counts.
+pub fn prose_text(source: &str) -> String {
+    text_content(source, false)
+}
+
+pub const MORE_MARKER: &str = "<!-- more -->";
+
+const EXCERPT_LENGTH: usize = 200;
+
+// Plain text summary of a post: every paragraph before `<!-- more -->` or,
+// without the marker, the first paragraphs up to about 200 characters.
+pub fn excerpt(source: &str) -> String {
+    let Ok(tree) = markdown::to_mdast(source, &markdown::ParseOptions::default()) else {
+        return String::new();
+    };
+    let Some(blocks) = tree.children() else {
+        return String::new();
+    };
+    let is_more = |block: &Node| matches!(block, Node::Html(html) if html.value.contains(MORE_MARKER));
+    let has_more = blocks.iter().any(is_more);
+
+    let mut excerpt = String::new();
+    for block in blocks {
+        if is_more(block) || (!has_more && excerpt.chars().count() >= EXCERPT_LENGTH) {
+            break;
+        }
+        if let Node::Paragraph(_) = block {
+            let mut text = String::new();
+            collect_text(block, &mut text, true);
+            for line in without_markers(&text).map(str::trim).filter(|line| !line.is_empty()) {
+                if !excerpt.is_empty() {
+                    excerpt.push(' ');
+                }
+                excerpt.push_str(line);
+            }
+        }
+    }
+
+    if has_more || excerpt.chars().count() <= EXCERPT_LENGTH + EXCERPT_LENGTH / 2 {
+        return excerpt;
+    }
+    let cut: String = excerpt.chars().take(EXCERPT_LENGTH).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
+
+fn visit(node: &Node, f: &mut impl FnMut(&Node)) {
+    f(node);
+    if let Some(children) = node.children() {
+        for child in children {
+            visit(child, f);
+        }
+    }
+}
+
+fn line(node: &Node) -> usize {
+    node.position().map_or(0, |position| position.start.line)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkTarget {
+    pub url: String,
+    pub image: bool,
+    pub line: usize,
+}
+
+// Every link and image in a post, including references and figures.
+pub fn links(source: &str) -> Vec<LinkTarget> {
+    let Ok(tree) = markdown::to_mdast(source, &markdown::ParseOptions::default()) else {
+        return Vec::new();
+    };
+
+    let mut definitions = std::collections::HashMap::new();
+    visit(&tree, &mut |node| {
+        if let Node::Definition(definition) = node {
+            definitions.insert(definition.identifier.clone(), definition.url.clone());
+        }
+    });
+
+    let mut links = Vec::new();
+    visit(&tree, &mut |node| {
+        let (url, image) = match node {
+            Node::Link(link) => (Some(link.url.clone()), false),
+            Node::Image(image) => (Some(image.url.clone()), true),
+            Node::LinkReference(link) => (definitions.get(&link.identifier).cloned(), false),
+            Node::ImageReference(image) => (definitions.get(&image.identifier).cloned(), true),
+            Node::Paragraph(_) => {
+                let mut text = String::new();
+                collect_text(node, &mut text, true);
+                for (offset, text_line) in text.lines().enumerate() {
+                    if let Some(Marker::Leaf(directive)) = parse_marker(text_line) {
+                        if let (Some(src), "figure") = (directive.attr("src"), directive.name.as_str()) {
+                            links.push(LinkTarget {
+                                url: src.to_string(),
+                                image: true,
+                                line: line(node) + offset,
+                            });
+                        }
+                    }
+                }
+                (None, false)
+            }
+            _ => (None, false),
+        };
+        if let Some(url) = url {
+            links.push(LinkTarget {
+                url,
+                image,
+                line: line(node),
+            });
+        }
+    });
+    links
+}
+
+// Language of every fenced code block, with its line.
+pub fn code_languages(source: &str) -> Vec<(String, usize)> {
+    let Ok(tree) = markdown::to_mdast(source, &markdown::ParseOptions::default()) else {
+        return Vec::new();
+    };
+
+    let mut languages = Vec::new();
+    visit(&tree, &mut |node| {
+        if let Node::Code(code) = node {
+            if let Some(lang) = &code.lang {
+                languages.push((lang.clone(), line(node)));
+            }
+        }
+    });
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str) -> String {
+        render_markdown(source, &Directives::default(), ContentSource::Post, None)
+    }
+
+    #[test]
+    fn renders_nested_containers() {
+        let html = render(":::warning\nAntes\n\n:::note[Dentro]\n**Nota**\n:::\n\nDespués\n:::\n");
+        let warning = html.find("callout-warning").unwrap();
+        let note = html.find("callout-note").unwrap();
+        let after = html.find("Después").unwrap();
+        assert!(warning < note && note < after);
+        assert!(html.contains("<strong>Nota</strong>"));
+        assert_eq!(html.matches("</aside>").count(), 2);
+        // The note is closed before `Después`, the warning after it.
+        assert!(html[note..after].contains("</aside>"));
+        assert!(html[after..].contains("</aside>"));
+    }
+
+    #[test]
+    fn unknown_containers_take_their_own_closer() {
+        let html = render(":::note\nAntes\n\n:::desconocida\nDentro\n:::\n\nDespués\n:::\n");
+        assert!(html.contains(":::desconocida"));
+        let after = html.find("Después").unwrap();
+        assert_eq!(html.matches("</aside>").count(), 1);
+        assert!(html[after..].contains("</aside>"));
+    }
+
+    #[test]
+    fn unclosed_containers_end_with_the_file() {
+        let html = render(":::note\nSin cerrar\n");
+        assert!(html.contains("callout-note"));
+        assert!(html.contains("Sin cerrar"));
+
+        let html = render(":::desconocida\nSin cerrar\n");
+        assert!(html.contains(":::desconocida"));
+        assert!(html.contains("Sin cerrar"));
+    }
+
+    #[test]
+    fn excerpt_stops_at_the_more_marker() {
+        let source = "# Título\n\nPrimero.\n\n```\ncódigo\n```\n\nSegundo *con énfasis*.\n\n<!-- more -->\n\nTercero.\n";
+        assert_eq!(excerpt(source), "Primero. Segundo con énfasis.");
+    }
+
+    #[test]
+    fn excerpt_without_marker_is_cut_at_a_word() {
+        let short = "Un párrafo.\n\n::youtube{id=abc}\n\nOtro párrafo.\n";
+        assert_eq!(excerpt(short), "Un párrafo. Otro párrafo.");
+
+        let long = format!("{}\n\nNo llega.\n", "palabra, ".repeat(40));
+        let cut = excerpt(&long);
+        assert!(cut.ends_with("palabra…"), "{cut}");
+        assert!(cut.chars().count() <= EXCERPT_LENGTH + 1);
+        assert!(!cut.contains("No llega"));
+    }
+
+    #[test]
+    fn markers_in_code_fences_are_left_alone() {
+        let html = render("```\n:::note\n:::\n```\n");
+        assert!(!html.contains("callout"));
+        assert!(html.contains(":::note"));
+    }
+}
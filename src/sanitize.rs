@@ -0,0 +1,80 @@
+use ammonia::{UrlRelative, UrlRelativeEvaluate};
+use std::borrow::Cow;
+
+// Rendered HTML is injected with `inner_html`, so everything goes through an
+// allow-list before reaching the page. How much is allowed depends on who
+// wrote the content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentSource {
+    // Posts from the `posts` directory: raw HTML, embeds and islands are kept.
+    Post,
+    // Anything written by readers (comments, guest posts): basic formatting only.
+    Guest,
+}
+
+const EMBED_HOSTS: &[&str] = &[
+    "https://www.youtube-nocookie.com/embed/",
+    "https://gist.github.com/",
+];
+
+impl ContentSource {
+    pub fn allows_raw_html(self) -> bool {
+        match self {
+            ContentSource::Post => true,
+            ContentSource::Guest => false,
+        }
+    }
+
+    fn builder(self) -> ammonia::Builder<'static> {
+        let mut builder = ammonia::Builder::default();
+        builder.url_schemes(["http", "https", "mailto"].into());
+
+        match self {
+            ContentSource::Post => {
+                builder
+                    .add_tags(["iframe"])
+                    .add_generic_attributes(["class", "id"])
+                    .add_tag_attributes("iframe", ["src", "title", "loading", "allowfullscreen"])
+                    .add_tag_attributes("div", ["data-island", "data-props"])
+                    .attribute_filter(|element, attribute, value| match (element, attribute) {
+                        ("iframe", "src") => EMBED_HOSTS
+                            .iter()
+                            .any(|host| value.starts_with(host))
+                            .then_some(Cow::Borrowed(value)),
+                        _ => Some(Cow::Borrowed(value)),
+                    });
+            }
+            ContentSource::Guest => {
+                builder.rm_tags(["img"]);
+            }
+        }
+
+        builder
+    }
+}
+
+pub fn sanitize(html: &str, source: ContentSource) -> String {
+    source.builder().clean(html).to_string()
+}
+
+// Relative URLs such as `foto.png` point to the files next to the post.
+struct PostAssets(String);
+
+impl<'a> UrlRelativeEvaluate<'a> for PostAssets {
+    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
+        if url.starts_with(['/', '#', '?']) {
+            Some(Cow::Borrowed(url))
+        } else {
+            Some(Cow::Owned(format!("{}{}", self.0, url.trim_start_matches("./"))))
+        }
+    }
+}
+
+// Like `sanitize`, with `assets` being something like `/post/hola.md/assets/`.
+pub fn sanitize_with_assets(html: &str, source: ContentSource, assets: &str) -> String {
+    source
+        .builder()
+        .url_relative(UrlRelative::Custom(Box::new(PostAssets(assets.to_string()))))
+        .clean(html)
+        .to_string()
+}
@@ -0,0 +1,111 @@
+use crate::search::fold;
+use serde::Serialize;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// Format of `fecha` in the metadata of every post.
+pub const DATE_FORMAT: &str = "%d/%m/%Y";
+
+#[derive(Serialize)]
+struct NewPostMeta<'a> {
+    titulo: &'a str,
+    descripcion: &'a str,
+    fecha: &'a str,
+    archivo: &'a str,
+    etiquetas: &'a [String],
+    draft: bool,
+}
+
+// "Título del post" becomes "titulo_del_post", like the existing directories.
+pub fn slug(title: &str) -> String {
+    fold(title)
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn template(title: &str) -> String {
+    format!(
+        "# {title}\n\
+         \n\
+         Introducción del post. Si `descripcion` queda vacía, los párrafos antes de\n\
+         la marca de abajo se usan como resumen.\n\
+         \n\
+         <!-- more -->\n\
+         \n\
+         ## Primera sección\n\
+         \n\
+         Contenido del post.\n"
+    )
+}
+
+// Creates `<root>/<slug>/` with the Markdown file and its metadata, marked as
+// a draft until `draft: true` is removed. Returns the new directory.
+pub fn new_post(root: &Path, title: &str) -> std::io::Result<PathBuf> {
+    let slug = slug(title);
+    if slug.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the title needs at least one letter or number",
+        ));
+    }
+
+    let dir = root.join(&slug);
+    if dir.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", dir.display()),
+        ));
+    }
+
+    let archivo = format!("{slug}.md");
+    let fecha = chrono::Local::now().format(DATE_FORMAT).to_string();
+    let meta = serde_yaml::to_string(&NewPostMeta {
+        titulo: title,
+        descripcion: "",
+        fecha: &fecha,
+        archivo: &archivo,
+        etiquetas: &[],
+        draft: true,
+    })
+    .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(&archivo), template(title))?;
+    fs::write(dir.join(format!("{slug}.yml")), meta)?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_fold_accents_and_punctuation() {
+        assert_eq!(slug("Título del post"), "titulo_del_post");
+        assert_eq!(slug("  ¿Qué es Rust? (parte 2)  "), "que_es_rust_parte_2");
+        assert_eq!(slug("Ñandú & Cía."), "nandu_cia");
+        assert_eq!(slug("¡¿?!"), "");
+    }
+
+    #[test]
+    fn creates_a_draft_with_its_metadata() {
+        let root = std::env::temp_dir().join(format!("blog-scaffold-{}", std::process::id()));
+        let dir = new_post(&root, "Hola, mundo").unwrap();
+        let meta = fs::read_to_string(dir.join("hola_mundo.yml")).unwrap();
+        let source = fs::read_to_string(dir.join("hola_mundo.md")).unwrap();
+        let again = new_post(&root, "Hola mundo").unwrap_err();
+        let empty = new_post(&root, "???").unwrap_err();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(dir, root.join("hola_mundo"));
+        assert!(meta.contains("titulo: Hola, mundo\n"));
+        assert!(meta.contains("archivo: hola_mundo.md\n"));
+        assert!(meta.contains("draft: true\n"));
+        assert!(source.starts_with("# Hola, mundo\n"));
+        assert_eq!(again.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(empty.kind(), ErrorKind::InvalidInput);
+    }
+}
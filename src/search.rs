@@ -0,0 +1,425 @@
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+use std::collections::{HashMap, HashSet};
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// BM25 over titles, tags, descriptions and bodies. Words are lowercased, stripped
+// of accents and stemmed in Spanish, both when indexing and when searching,
+// so "programacion" finds "Programación". Indexes are built by the server,
+// searched by the server and, for static sites, by the browser.
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+const K1: f32 = 1.2;
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+const B: f32 = 0.75;
+#[cfg(feature = "ssr")]
+const TITLE_WEIGHT: f32 = 3.0;
+#[cfg(feature = "ssr")]
+const TAG_WEIGHT: f32 = 2.0;
+#[cfg(feature = "ssr")]
+const DESCRIPTION_WEIGHT: f32 = 2.0;
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+const SNIPPET_WORDS: usize = 30;
+
+// Served by the server and written by the static export, so the browser can
+// search without calling a server function.
+pub const SEARCH_INDEX_PATH: &str = "/search-index.json";
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+const STOP_WORDS: &[&str] = &[
+    "a", "al", "como", "con", "de", "del", "el", "en", "es", "esta", "este", "la", "las", "le",
+    "lo", "los", "mas", "me", "mi", "muy", "no", "o", "para", "pero", "por", "que", "se", "si",
+    "sin", "son", "su", "sus", "un", "una", "uno", "y", "ya",
+];
+
+#[cfg(feature = "ssr")]
+pub struct SearchDocument<'a> {
+    pub archivo: &'a str,
+    pub titulo: &'a str,
+    pub fecha: &'a str,
+    pub etiquetas: &'a [String],
+    pub descripcion: &'a str,
+    // Plain text of the post, also used for snippets.
+    pub body: &'a str,
+}
+
+// The serialised index has the postings and a short summary of each post,
+// full texts stay on the server for better snippets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    archivo: String,
+    titulo: String,
+    fecha: String,
+    summary: String,
+    #[cfg(feature = "ssr")]
+    #[serde(skip)]
+    text: String,
+    length: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    // Term to (document, weighted term frequency).
+    postings: BTreeMap<String, Vec<(u32, f32)>>,
+    average_length: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub archivo: String,
+    pub titulo: String,
+    pub fecha: String,
+    pub score: f32,
+    pub snippet: Vec<SnippetPart>,
+}
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+pub fn fold(word: &str) -> String {
+    word.to_lowercase()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+fn term(stemmer: &Stemmer, word: &str) -> Option<String> {
+    let folded = fold(word);
+    if STOP_WORDS.contains(&folded.as_str()) {
+        return None;
+    }
+    Some(stemmer.stem(&folded).into_owned())
+}
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+pub fn terms(text: &str) -> Vec<String> {
+    let stemmer = Stemmer::create(Algorithm::Spanish);
+    words(text)
+        .filter_map(|(_, word)| term(&stemmer, word))
+        .collect()
+}
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+impl Document {
+    fn text(&self) -> &str {
+        #[cfg(feature = "ssr")]
+        return &self.text;
+        #[cfg(not(feature = "ssr"))]
+        return &self.summary;
+    }
+}
+
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+impl SearchIndex {
+    #[cfg(feature = "ssr")]
+    pub fn build<'a>(documents: impl IntoIterator<Item = SearchDocument<'a>>) -> Self {
+        let mut index = SearchIndex::default();
+        let mut total_length = 0.0;
+
+        for (id, document) in documents.into_iter().enumerate() {
+            let mut frequencies: HashMap<String, f32> = HashMap::new();
+            let mut length = 0.0;
+            let tags = document.etiquetas.iter().map(|tag| (TAG_WEIGHT, tag.as_str()));
+            let fields = [
+                (TITLE_WEIGHT, document.titulo),
+                (DESCRIPTION_WEIGHT, document.descripcion),
+                (1.0, document.body),
+            ];
+            for (weight, text) in tags.chain(fields) {
+                for term in terms(text) {
+                    *frequencies.entry(term).or_default() += weight;
+                    length += weight;
+                }
+            }
+
+            for (term, frequency) in frequencies {
+                index
+                    .postings
+                    .entry(term)
+                    .or_default()
+                    .push((id as u32, frequency));
+            }
+            total_length += length;
+            index.documents.push(Document {
+                archivo: document.archivo.to_string(),
+                titulo: document.titulo.to_string(),
+                fecha: document.fecha.to_string(),
+                summary: document.descripcion.to_string(),
+                text: document.body.to_string(),
+                length,
+            });
+        }
+
+        if !index.documents.is_empty() {
+            index.average_length = total_length / index.documents.len() as f32;
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let query_terms: HashSet<String> = terms(query).into_iter().collect();
+        let count = self.documents.len() as f32;
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+
+        for term in &query_terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let frequency = postings.len() as f32;
+            let idf = ((count - frequency + 0.5) / (frequency + 0.5) + 1.0).ln();
+            for &(id, tf) in postings {
+                let length = self.documents[id as usize].length / self.average_length;
+                *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length));
+            }
+        }
+
+        let mut scores: Vec<(u32, f32)> = scores.into_iter().collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores
+            .into_iter()
+            .map(|(id, score)| {
+                let document = &self.documents[id as usize];
+                SearchHit {
+                    archivo: document.archivo.clone(),
+                    titulo: document.titulo.clone(),
+                    fecha: document.fecha.clone(),
+                    score,
+                    snippet: snippet(document.text(), &query_terms),
+                }
+            })
+            .collect()
+    }
+}
+
+// The window of `SNIPPET_WORDS` words with the most matches, split in parts
+// so the matches can be highlighted without building HTML.
+#[cfg(any(feature = "ssr", feature = "hydrate"))]
+fn snippet(text: &str, query_terms: &HashSet<String>) -> Vec<SnippetPart> {
+    let stemmer = Stemmer::create(Algorithm::Spanish);
+    let words: Vec<(usize, &str, bool)> = words(text)
+        .map(|(offset, word)| {
+            let hit = term(&stemmer, word).is_some_and(|term| query_terms.contains(&term));
+            (offset, word, hit)
+        })
+        .collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let (mut start, mut best, mut matches) = (0, 0, 0);
+    for i in 0..words.len() {
+        if words[i].2 {
+            matches += 1;
+        }
+        if i >= SNIPPET_WORDS && words[i - SNIPPET_WORDS].2 {
+            matches -= 1;
+        }
+        if matches > best {
+            best = matches;
+            start = (i + 1).saturating_sub(SNIPPET_WORDS);
+        }
+    }
+    // The window found ends at a match, move it so the matches in it are in
+    // the middle.
+    let window = &words[start..(start + SNIPPET_WORDS).min(words.len())];
+    if let (Some(first), Some(last)) = (
+        window.iter().position(|word| word.2),
+        window.iter().rposition(|word| word.2),
+    ) {
+        let slack = SNIPPET_WORDS - (last - first + 1);
+        start = (start + first)
+            .saturating_sub(slack / 2)
+            .min(words.len().saturating_sub(SNIPPET_WORDS));
+    }
+    let end = (start + SNIPPET_WORDS).min(words.len());
+
+    let mut parts = Vec::new();
+    let mut push = |text: &str, highlight: bool| {
+        // Line breaks and indentation become single spaces.
+        let mut collapsed = String::new();
+        let mut space = false;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                space = true;
+                continue;
+            }
+            if space {
+                collapsed.push(' ');
+                space = false;
+            }
+            collapsed.push(c);
+        }
+        if space {
+            collapsed.push(' ');
+        }
+        if !collapsed.is_empty() {
+            parts.push(SnippetPart {
+                text: collapsed,
+                highlight,
+            });
+        }
+    };
+
+    if start > 0 {
+        push("… ", false);
+    }
+    let mut cursor = words[start].0;
+    for &(offset, word, hit) in &words[start..end] {
+        if hit {
+            push(&text[cursor..offset], false);
+            push(word, true);
+            cursor = offset + word.len();
+        }
+    }
+    let (offset, word, _) = words[end - 1];
+    push(&text[cursor..offset + word.len()], false);
+    if end < words.len() {
+        push(" …", false);
+    }
+
+    parts
+}
+
+#[cfg(feature = "hydrate")]
+thread_local! {
+    static CLIENT_INDEX: std::cell::RefCell<Option<std::rc::Rc<SearchIndex>>> = const { std::cell::RefCell::new(None) };
+}
+
+// Downloads the serialised index once and keeps it for later searches.
+#[cfg(feature = "hydrate")]
+pub async fn client_index() -> Option<std::rc::Rc<SearchIndex>> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    if let Some(index) = CLIENT_INDEX.with(|index| index.borrow().clone()) {
+        return Some(index);
+    }
+
+    let window = web_sys::window()?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(SEARCH_INDEX_PATH))
+        .await
+        .ok()?
+        .dyn_into()
+        .ok()?;
+    if !response.ok() {
+        return None;
+    }
+    let text = JsFuture::from(response.text().ok()?).await.ok()?.as_string()?;
+    let index = std::rc::Rc::new(serde_json::from_str::<SearchIndex>(&text).ok()?);
+
+    CLIENT_INDEX.with(|current| *current.borrow_mut() = Some(index.clone()));
+    Some(index)
+}
+
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    fn document<'a>(archivo: &'a str, titulo: &'a str, etiquetas: &'a [String], body: &'a str) -> SearchDocument<'a> {
+        SearchDocument {
+            archivo,
+            titulo,
+            fecha: "01/01/2024",
+            etiquetas,
+            descripcion: "",
+            body,
+        }
+    }
+
+    fn found(index: &SearchIndex, query: &str) -> Vec<String> {
+        index.search(query).into_iter().map(|hit| hit.archivo).collect()
+    }
+
+    #[test]
+    fn folds_case_and_accents() {
+        assert_eq!(fold("Programación ÁGIL"), "programacion agil");
+        assert_eq!(terms("Programación"), terms("programacion"));
+        assert_eq!(terms("programar programando"), vec!["program", "program"]);
+    }
+
+    #[test]
+    fn leaves_out_stop_words() {
+        assert_eq!(terms("el blog de la semana"), terms("blog semana"));
+        assert!(terms("y de la").is_empty());
+    }
+
+    #[test]
+    fn titles_rank_above_bodies() {
+        let index = SearchIndex::build([
+            document("cuerpo.md", "Otra cosa", &[], "Aquí se habla de Rust de pasada."),
+            document("titulo.md", "Rust en la web", &[], "Un post sobre servidores."),
+            document("nada.md", "Cocina", &[], "Recetas de cocina."),
+        ]);
+        assert_eq!(found(&index, "rust"), ["titulo.md", "cuerpo.md"]);
+        assert!(found(&index, "python").is_empty());
+    }
+
+    #[test]
+    fn finds_posts_by_tag() {
+        let tags = ["leptos".to_string()];
+        let index = SearchIndex::build([
+            document("con.md", "Un post", &tags, "Texto."),
+            document("sin.md", "Otro post", &[], "Texto."),
+        ]);
+        assert_eq!(found(&index, "Leptos"), ["con.md"]);
+    }
+
+    #[test]
+    fn serialised_index_leaves_bodies_out() {
+        let document = SearchDocument {
+            descripcion: "Un resumen corto.",
+            ..document("a.md", "Título", &[], "Un cuerpo larguísimo sobre ferrocarriles.")
+        };
+        let json = serde_json::to_string(&SearchIndex::build([document])).unwrap();
+        assert!(json.contains("Un resumen corto."));
+        assert!(!json.contains("larguísimo"));
+
+        // Still found by words in the body, through the postings.
+        let index: SearchIndex = serde_json::from_str(&json).unwrap();
+        assert_eq!(found(&index, "ferrocarril"), ["a.md"]);
+    }
+
+    #[test]
+    fn snippets_highlight_matches() {
+        let body = format!("{} La búsqueda encuentra\n  palabras. {}", "relleno ".repeat(40), "final ".repeat(40));
+        let index = SearchIndex::build([document("a.md", "A", &[], &body)]);
+        let snippet = &index.search("busqueda")[0].snippet;
+
+        let highlighted: Vec<&str> = snippet
+            .iter()
+            .filter(|part| part.highlight)
+            .map(|part| part.text.as_str())
+            .collect();
+        assert_eq!(highlighted, ["búsqueda"]);
+        let text: String = snippet.iter().map(|part| part.text.as_str()).collect();
+        assert!(text.starts_with("… "));
+        assert!(text.ends_with(" …"));
+        assert!(text.contains("búsqueda encuentra palabras"));
+        assert!(text.split_whitespace().count() <= SNIPPET_WORDS + 2);
+    }
+}
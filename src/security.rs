@@ -0,0 +1,196 @@
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::OnceLock;
+
+// Headers sent with every response. Pages put the policy in the header
+// themselves, with the nonce of their inline scripts (see `App`); anything
+// else gets it here, without one.
+
+pub const NONCE: &str = "{nonce}";
+
+// The embeds in `crate::sanitize::EMBED_HOSTS` are the only frames.
+pub const DEFAULT_CSP: &str = "default-src 'self'; \
+    script-src 'nonce-{nonce}' 'strict-dynamic' 'wasm-unsafe-eval'; \
+    style-src 'self'; img-src 'self' https: data:; connect-src 'self'; \
+    frame-src https://www.youtube-nocookie.com https://gist.github.com; \
+    object-src 'none'; base-uri 'self'; form-action 'self'; frame-ancestors 'none'";
+pub const DEFAULT_HSTS: &str = "max-age=31536000";
+pub const DEFAULT_REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+pub const DEFAULT_PERMISSIONS_POLICY: &str = "camera=(), geolocation=(), microphone=(), payment=(), usb=()";
+
+// An empty value leaves the header out.
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    // With `{nonce}` where the nonce of the page goes.
+    pub content_security_policy: String,
+    // Only report violations, to try a new policy out.
+    pub csp_report_only: bool,
+    pub strict_transport_security: String,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+    // The development server reloads the page through a WebSocket.
+    pub live_reload: bool,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            content_security_policy: DEFAULT_CSP.to_string(),
+            csp_report_only: false,
+            strict_transport_security: DEFAULT_HSTS.to_string(),
+            referrer_policy: DEFAULT_REFERRER_POLICY.to_string(),
+            permissions_policy: DEFAULT_PERMISSIONS_POLICY.to_string(),
+            live_reload: false,
+        }
+    }
+}
+
+static CONFIG: OnceLock<SecurityConfig> = OnceLock::new();
+
+// Set once at startup from the command line.
+pub fn configure(config: SecurityConfig) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn config() -> &'static SecurityConfig {
+    CONFIG.get_or_init(SecurityConfig::default)
+}
+
+pub fn csp_header() -> HeaderName {
+    if config().csp_report_only {
+        header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+    } else {
+        header::CONTENT_SECURITY_POLICY
+    }
+}
+
+// The policy for a page with inline scripts carrying `nonce`, or for
+// anything else, where sources with a nonce are left out.
+pub fn content_security_policy(nonce: Option<&str>) -> Option<HeaderValue> {
+    let config = config();
+    let mut directives = Vec::new();
+    for directive in config.content_security_policy.split(';') {
+        let mut sources: Vec<String> = directive
+            .split_whitespace()
+            .filter_map(|source| match nonce {
+                Some(nonce) => Some(source.replace(NONCE, nonce)),
+                None if source.contains(NONCE) => None,
+                None => Some(source.to_string()),
+            })
+            .collect();
+        if config.live_reload && sources.first().is_some_and(|name| name == "connect-src") {
+            sources.push("ws:".to_string());
+        }
+        if !sources.is_empty() {
+            directives.push(sources.join(" "));
+        }
+    }
+    if directives.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&directives.join("; ")).ok()
+}
+
+// Whether the request has `Authorization: Bearer <token>`, for the admin
+// endpoints. Compared in constant time, it is a password after all.
+pub fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(given) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn set(response: &mut Response, name: HeaderName, value: &str) {
+    if value.is_empty() || response.headers().contains_key(&name) {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(value) {
+        response.headers_mut().insert(name, value);
+    }
+}
+
+pub async fn security_headers(request: Request, next: Next) -> Response {
+    let config = config();
+    let mut response = next.run(request).await;
+
+    // A 304 would replace the policy the browser keeps with the page, and
+    // with it the nonce of its scripts.
+    let csp = csp_header();
+    if response.status() != StatusCode::NOT_MODIFIED && !response.headers().contains_key(&csp) {
+        if let Some(policy) = content_security_policy(None) {
+            response.headers_mut().insert(csp, policy);
+        }
+    }
+    set(&mut response, header::STRICT_TRANSPORT_SECURITY, &config.strict_transport_security);
+    set(&mut response, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    set(&mut response, header::REFERRER_POLICY, &config.referrer_policy);
+    set(&mut response, HeaderName::from_static("permissions-policy"), &config.permissions_policy);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn pages_get_their_nonce_in_the_policy() {
+        let policy = content_security_policy(Some("abc")).unwrap();
+        let policy = policy.to_str().unwrap();
+        assert!(policy.contains("script-src 'nonce-abc' 'strict-dynamic'"));
+        assert!(!policy.contains(NONCE));
+    }
+
+    #[test]
+    fn sources_with_a_nonce_are_left_out_without_one() {
+        let policy = content_security_policy(None).unwrap();
+        let policy = policy.to_str().unwrap();
+        assert!(policy.contains("script-src 'strict-dynamic' 'wasm-unsafe-eval';"));
+        assert!(!policy.contains("nonce"));
+        assert!(policy.starts_with("default-src 'self'; "));
+    }
+
+    async fn headers_of(app: Router) -> Response {
+        let app = app.layer(middleware::from_fn(security_headers));
+        app.oneshot(Request::get("/").body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_response_gets_the_headers() {
+        let response = headers_of(Router::new().route("/", get(|| async { "hola" }))).await;
+        let headers = response.headers();
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], DEFAULT_HSTS);
+        assert_eq!(headers[header::REFERRER_POLICY], DEFAULT_REFERRER_POLICY);
+        assert_eq!(headers["permissions-policy"], DEFAULT_PERMISSIONS_POLICY);
+        assert_eq!(headers[csp_header()], content_security_policy(None).unwrap());
+    }
+
+    #[tokio::test]
+    async fn pages_keep_their_policy_and_304s_get_none() {
+        let page = Router::new().route(
+            "/",
+            get(|| async { ([(header::CONTENT_SECURITY_POLICY, "script-src 'nonce-abc'")], "hola") }),
+        );
+        let not_modified = Router::new().route("/", get(|| async { StatusCode::NOT_MODIFIED }));
+
+        let page = headers_of(page).await;
+        let not_modified = headers_of(not_modified).await;
+        assert_eq!(page.headers()[header::CONTENT_SECURITY_POLICY], "script-src 'nonce-abc'");
+        assert!(!not_modified.headers().contains_key(header::CONTENT_SECURITY_POLICY));
+    }
+}
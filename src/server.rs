@@ -0,0 +1,45 @@
+use crate::app::App;
+use crate::assets::PAGE;
+use crate::conditional::{conditional_get, Build};
+use crate::page_cache::{page_cache, purge_handler, PageCache, PURGE_PATH};
+use crate::fileserv::{
+    file_and_error_handler, image_variant_handler, og_image_handler, post_asset_handler,
+    search_index_handler,
+};
+use crate::search::SEARCH_INDEX_PATH;
+use axum::http::{header, HeaderValue};
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::Router;
+use leptos::LeptosOptions;
+use leptos_axum::{generate_route_list, LeptosRoutes};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+// The whole site as an axum router, shared by the server and the static export.
+pub fn router(leptos_options: LeptosOptions, cache: Option<PageCache>) -> Router {
+    let routes = generate_route_list(App);
+    let build = Build::new(&leptos_options);
+
+    let mut router = Router::new()
+        .route(SEARCH_INDEX_PATH, get(search_index_handler))
+        .route("/post/:archivo/assets/*path", get(post_asset_handler))
+        .route("/post/:archivo/images/:width/*path", get(image_variant_handler))
+        .route("/og/:file", get(og_image_handler))
+        .leptos_routes(&leptos_options, routes, App)
+        .fallback(file_and_error_handler)
+        .with_state(leptos_options);
+    if let Some(cache) = cache {
+        router = router.layer(middleware::from_fn_with_state(cache.clone(), page_cache));
+        if cache.has_admin() {
+            router = router.route(PURGE_PATH, post(purge_handler).with_state(cache));
+        }
+    }
+
+    router
+        .layer(middleware::from_fn_with_state(build, conditional_get))
+        // Pages and anything else that didn't set its own caching.
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(PAGE),
+        ))
+}
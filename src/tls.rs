@@ -0,0 +1,195 @@
+use axum::extract::Host;
+use axum::http::uri::Authority;
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// HTTPS with rustls, over HTTP/1.1 or HTTP/2 as the browser prefers. The
+// certificate is read again whenever its files change, so renewing it
+// doesn't need a restart.
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub async fn rustls_config(tls: &TlsConfig) -> std::io::Result<RustlsConfig> {
+    // reqwest brings in ring too, use it everywhere.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(&tls.cert, &tls.key).await
+}
+
+// Checks the certificate files every few seconds, and loads them when they
+// change. A certificate that doesn't load keeps the old one in use.
+pub fn reload_on_change(config: RustlsConfig, tls: TlsConfig) {
+    tokio::spawn(async move {
+        let mut loaded = (modified(&tls.cert), modified(&tls.key));
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            let current = (modified(&tls.cert), modified(&tls.key));
+            if current == loaded {
+                continue;
+            }
+            loaded = current;
+            match config.reload_from_pem_file(&tls.cert, &tls.key).await {
+                Ok(()) => leptos::logging::log!("reloaded the certificate from {}", tls.cert.display()),
+                Err(err) => leptos::logging::error!(
+                    "could not reload the certificate from {}: {err}",
+                    tls.cert.display()
+                ),
+            }
+        }
+    });
+}
+
+fn https_uri(host: &str, uri: &Uri, https_port: u16) -> Option<Uri> {
+    // `Host` may carry the port of the plain HTTP listener, after the
+    // brackets of an IPv6 address.
+    let host = match host.find(']') {
+        Some(end) => &host[..=end],
+        None => host.split(':').next().unwrap_or(host),
+    };
+    let authority = match https_port {
+        443 => host.to_string(),
+        port => format!("{host}:{port}"),
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    format!("https://{authority}{path}").parse().ok()
+}
+
+// The host and port of the site, when its URL is https.
+pub fn public_authority(site_url: &str) -> Option<Authority> {
+    let uri: Uri = site_url.parse().ok()?;
+    if uri.scheme_str() != Some("https") {
+        return None;
+    }
+    uri.authority().cloned()
+}
+
+// Plain HTTP listener that sends every request to the same URL over HTTPS,
+// on `public` when it is known. Otherwise on the host the browser asked for
+// and the port of the HTTPS listener, which behind a proxy or a container
+// port mapping may not be the one the world sees.
+pub async fn redirect_to_https(addr: SocketAddr, public: Option<Authority>, https_port: u16) {
+    let redirect = move |Host(host): Host, uri: Uri| {
+        let target = match &public {
+            Some(public) => https_uri(public.as_str(), &uri, public.port_u16().unwrap_or(443)),
+            None => https_uri(&host, &uri, https_port),
+        };
+        async move {
+            match target {
+                Some(uri) => Redirect::permanent(&uri.to_string()).into_response(),
+                None => StatusCode::BAD_REQUEST.into_response(),
+            }
+        }
+    };
+    let app: Router = Router::new().fallback(redirect);
+
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            leptos::logging::error!("could not listen on {addr} for redirects: {err}");
+            return;
+        }
+    };
+    leptos::logging::log!("redirecting http://{addr} to https");
+    if let Err(err) = axum::serve(listener, app.into_make_service()).await {
+        leptos::logging::error!("redirect listener failed: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum_server::Handle;
+
+    fn redirect(host: &str, uri: &str, port: u16) -> Option<String> {
+        https_uri(host, &uri.parse().unwrap(), port).map(|uri| uri.to_string())
+    }
+
+    #[test]
+    fn redirects_keep_the_path_and_query() {
+        assert_eq!(
+            redirect("blog.example.com", "/search?q=rust&x=1", 443).as_deref(),
+            Some("https://blog.example.com/search?q=rust&x=1")
+        );
+        assert_eq!(redirect("blog.example.com", "/", 443).as_deref(), Some("https://blog.example.com/"));
+    }
+
+    #[test]
+    fn redirects_replace_the_port_of_the_host() {
+        assert_eq!(
+            redirect("localhost:8080", "/post/hola.md", 8443).as_deref(),
+            Some("https://localhost:8443/post/hola.md")
+        );
+        assert_eq!(redirect("localhost:8080", "/", 443).as_deref(), Some("https://localhost/"));
+        assert_eq!(redirect("[::1]:8080", "/", 8443).as_deref(), Some("https://[::1]:8443/"));
+        assert_eq!(redirect("[::1]", "/", 443).as_deref(), Some("https://[::1]/"));
+    }
+
+    #[test]
+    fn redirects_refuse_invalid_hosts() {
+        assert_eq!(redirect("bad host", "/", 443), None);
+    }
+
+    #[test]
+    fn only_https_site_urls_give_the_public_host() {
+        let authority = |url| public_authority(url).map(|authority| authority.to_string());
+        assert_eq!(authority("https://blog.example.com").as_deref(), Some("blog.example.com"));
+        assert_eq!(authority("https://blog.example.com:8443/").as_deref(), Some("blog.example.com:8443"));
+        assert_eq!(authority("http://blog.example.com"), None);
+        assert_eq!(authority("blog.example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn serves_https_with_the_certificate() {
+        let dir = std::env::temp_dir().join(format!("blog-tls-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let tls = TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        };
+        fs::write(&tls.cert, certified.cert.pem()).unwrap();
+        fs::write(&tls.key, certified.key_pair.serialize_pem()).unwrap();
+        let config = rustls_config(&tls).await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let app: Router = Router::new().route("/", axum::routing::get(|| async { "hola" }));
+        let handle = Handle::new();
+        let server = axum_server::bind_rustls("127.0.0.1:0".parse().unwrap(), config)
+            .handle(handle.clone())
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        let addr = handle.listening().await.unwrap();
+
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(certified.cert.pem().as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("https://localhost:{}/", addr.port()))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.text().await.unwrap();
+        handle.shutdown();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "hola");
+    }
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "ssr")]
+
+use blog::directives::Directives;
+use blog::render::render_markdown;
+use blog::sanitize::{sanitize, ContentSource};
+
+#[test]
+fn strips_script_tags() {
+    let html = sanitize("<p>hola</p><script>alert(1)</script>", ContentSource::Post);
+    assert_eq!(html, "<p>hola</p>");
+}
+
+#[test]
+fn strips_event_handlers() {
+    let html = sanitize(
+        r#"<img src="/foto.png" onerror="alert(1)"><p onclick="alert(1)">hola</p>"#,
+        ContentSource::Post,
+    );
+    assert!(!html.contains("onerror"));
+    assert!(!html.contains("onclick"));
+    assert!(html.contains(r#"src="/foto.png""#));
+}
+
+#[test]
+fn strips_javascript_urls() {
+    let html = sanitize(
+        r#"<a href="javascript:alert(1)">a</a><a href="JaVaScRiPt:alert(1)">b</a><img src="javascript:alert(1)">"#,
+        ContentSource::Post,
+    );
+    assert!(!html.to_lowercase().contains("javascript"));
+}
+
+#[test]
+fn keeps_allowed_embeds_only() {
+    let html = sanitize(
+        r#"<iframe src="https://www.youtube-nocookie.com/embed/abc"></iframe><iframe src="https://evil.example/x"></iframe>"#,
+        ContentSource::Post,
+    );
+    assert!(html.contains("youtube-nocookie.com/embed/abc"));
+    assert!(!html.contains("evil.example"));
+}
+
+#[test]
+fn guest_content_is_restricted() {
+    let html = sanitize(
+        r#"<p class="x">hola</p><iframe src="https://www.youtube-nocookie.com/embed/abc"></iframe><img src="/a.png">"#,
+        ContentSource::Guest,
+    );
+    assert_eq!(html, "<p>hola</p>");
+}
+
+#[test]
+fn raw_html_in_posts_is_sanitised() {
+    let markdown = "Hola <span onmouseover=\"alert(1)\">mundo</span>\n\n<script>alert(1)</script>\n\n[link](javascript:alert(1))\n";
+    let html = render_markdown(markdown, &Directives::default(), ContentSource::Post, None);
+    assert!(html.contains("<span>mundo</span>"));
+    assert!(!html.contains("script"));
+    assert!(!html.contains("onmouseover"));
+    assert!(!html.contains("javascript"));
+}
+
+#[test]
+fn raw_html_from_guests_is_escaped() {
+    let html = render_markdown("<b>hola</b>", &Directives::empty(), ContentSource::Guest, None);
+    assert_eq!(html, "<p>&lt;b&gt;hola&lt;/b&gt;</p>\n");
+}
+
+#[test]
+fn directives_survive_sanitising() {
+    let markdown = ":::note\nContenido\n:::\n\n::island{name=Counter start=3}\n";
+    let html = render_markdown(markdown, &Directives::default(), ContentSource::Post, None);
+    assert!(html.contains(r#"class="callout callout-note""#));
+    assert!(html.contains(r#"data-island="Counter""#));
+    assert!(html.contains("data-props="));
+}
+
+#[test]
+fn relative_urls_point_to_post_assets() {
+    let markdown = "![foto](foto.png) [pdf](./docs/a.pdf) [otro](/post/otro.md) [arriba](#inicio)\n";
+    let html = render_markdown(
+        markdown,
+        &Directives::default(),
+        ContentSource::Post,
+        Some("/post/hola.md/assets/"),
+    );
+    assert!(html.contains(r#"src="/post/hola.md/assets/foto.png""#));
+    assert!(html.contains(r#"href="/post/hola.md/assets/docs/a.pdf""#));
+    assert!(html.contains(r#"href="/post/otro.md""#));
+    assert!(html.contains(r##"href="#inicio""##));
+}